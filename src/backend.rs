@@ -0,0 +1,715 @@
+//! OCR engines, recognizing the text of a cell image.
+//!
+//! External engines are run as subprocesses, given the cell image as a temporary
+//! file or on their standard input, their text read back from the file they
+//! write or their standard output (with confidences when it is tesseract's TSV or
+//! hOCR output). In-process engines implement
+//! `OcrBackend` directly and are added as a variant of `BackendConfig`.
+
+use std::{
+    fs,
+    io::{Cursor, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use egui_inspect::{
+    egui::{self, ComboBox, DragValue, TextEdit},
+    EguiInspect,
+};
+use image::{ImageFormat, RgbaImage};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    confidence,
+    error::{AtPath, Error, Result},
+    pdf,
+    workspace::Workspace,
+    CellSpan,
+};
+
+/// Text read from a cell.
+#[derive(Clone, Debug, Default)]
+pub struct Recognition {
+    pub text: String,
+    /// Mean confidence (in percent) of the words, for engines reporting one.
+    pub confidence: Option<f32>,
+}
+
+impl Recognition {
+    pub fn plain(text: String) -> Self {
+        Self {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+/// What the cells of an extraction share besides the backend.
+pub struct RunContext {
+    /// Where the files exchanged with external engines go.
+    pub workspace: Workspace,
+    /// Longest an engine may take on a cell before it is killed.
+    pub timeout: Option<Duration>,
+    /// Set to stop the extraction, killing the engines running.
+    pub cancel: Arc<AtomicBool>,
+    /// Threads each engine process may use, through `OMP_THREAD_LIMIT`, the
+    /// engine's own default when `None`.
+    pub engine_threads: Option<usize>,
+    /// Resolution the page was rendered at, for PDF pages.
+    pub dpi: Option<f32>,
+}
+
+impl RunContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+/// An OCR engine, called on the cells of a table in parallel.
+pub trait OcrBackend: Sync {
+    /// Reads the text of the crop of `cell`, giving up with `Error::Cancelled`
+    /// once `run` is cancelled.
+    fn recognize(
+        &self,
+        image: &RgbaImage,
+        cell: &CellSpan,
+        run: &RunContext,
+    ) -> Result<Recognition>;
+}
+
+/// Seconds an engine may take on a cell by default before it is taken as hung.
+pub const DEFAULT_CELL_TIMEOUT: f32 = 60.0;
+
+/// Timeout of `secs` seconds, none for 0.
+pub fn timeout(secs: f32) -> Option<Duration> {
+    (secs > 0.0).then(|| Duration::from_secs_f32(secs))
+}
+
+/// Threads of each engine process by default. Cells already run in parallel, and
+/// multithreaded engines (tesseract's OpenMP) running one per worker would
+/// oversubscribe the cores.
+pub const DEFAULT_ENGINE_THREADS: usize = 1;
+
+/// Thread limit of engine processes, none (the engine's default) for 0.
+pub fn engine_threads(threads: usize) -> Option<usize> {
+    (threads > 0).then_some(threads)
+}
+
+/// How often a running engine is checked for having finished, timed out or been
+/// cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How an engine is given the cell image and gives back its text.
+struct EngineIo {
+    /// Path the image is saved to before running the engine.
+    img_in: Option<String>,
+    /// Encoded image piped to the engine's standard input.
+    stdin: Option<Vec<u8>>,
+    /// Path (without extension) the engine writes its text to, with the extension
+    /// of the output format (txt, tsv or hocr). Its standard output is read
+    /// otherwise.
+    txt_out: Option<String>,
+    /// Leave the files in place after the run, for inspection.
+    keep: bool,
+}
+
+/// Runs `prog`, feeding it `stdin` and capturing its standard output if asked to,
+/// killed when it runs past the timeout or the extraction is cancelled.
+fn run_child(
+    prog: &str,
+    args: impl Iterator<Item = String>,
+    stdin: Option<Vec<u8>>,
+    capture: bool,
+    run: &RunContext,
+) -> Result<(ExitStatus, Vec<u8>)> {
+    let backend_error = |e| Error::Backend(prog.to_string(), e);
+    let mut command = Command::new(prog);
+    if let Some(threads) = run.engine_threads {
+        command.env("OMP_THREAD_LIMIT", threads.to_string());
+    }
+    let mut child = command
+        .args(args)
+        .stdin(match stdin.is_some() {
+            true => Stdio::piped(),
+            false => Stdio::null(),
+        })
+        .stdout(match capture {
+            true => Stdio::piped(),
+            false => Stdio::null(),
+        })
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(backend_error)?;
+    // piped from other threads, so that a child printing before it has read all of
+    // its input cannot block on a full pipe
+    let writer = stdin
+        .zip(child.stdin.take())
+        .map(|(bytes, mut pipe)| thread::spawn(move || pipe.write_all(&bytes)));
+    let reader = child.stdout.take().map(|mut pipe| {
+        thread::spawn(move || {
+            let mut out = vec![];
+            let _ = pipe.read_to_end(&mut out);
+            out
+        })
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(backend_error)? {
+            break status;
+        }
+        let timed_out = run.timeout.filter(|&timeout| start.elapsed() > timeout);
+        if timed_out.is_some() || run.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(match timed_out {
+                Some(timeout) => Error::Timeout(prog.to_string(), timeout),
+                None => Error::Cancelled,
+            });
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if let Some(writer) = writer {
+        // an engine that stopped reading early is judged by its exit status
+        let _ = writer.join();
+    }
+    let out = reader.map_or(vec![], |reader| reader.join().unwrap_or_default());
+    Ok((status, out))
+}
+
+/// Text an engine printed, tesseract's TSV and hOCR outputs told apart from plain
+/// text by their content.
+fn parse_printed(out: &str) -> Recognition {
+    if out.starts_with("level\tpage_num") {
+        confidence::parse_tsv(out)
+    } else if out.contains("ocrx_word") {
+        confidence::parse_hocr(out)
+    } else {
+        Recognition::plain(out.to_string())
+    }
+}
+
+/// Runs an engine with `args` (the program first) on the cell image.
+fn run_engine(
+    args: Vec<String>,
+    image: &RgbaImage,
+    io: EngineIo,
+    run: &RunContext,
+) -> Result<Recognition> {
+    if let Some(img_in) = &io.img_in {
+        image.save(img_in).at(img_in)?;
+    }
+
+    let mut args = args.into_iter();
+    let prog = args.next().ok_or("empty OCR command")?;
+    let outputs = io
+        .txt_out
+        .as_ref()
+        .map(|txt_out| ["txt", "tsv", "hocr"].map(|ext| format!("{txt_out}.{ext}")));
+    let output = run_child(&prog, args, io.stdin, outputs.is_none(), run);
+
+    let recognition = match output {
+        Ok((status, out)) if status.success() => match &outputs {
+            Some([txt, tsv, hocr]) => {
+                if Path::new(tsv).exists() {
+                    fs::read_to_string(tsv)
+                        .at(tsv)
+                        .map(|s| confidence::parse_tsv(&s))
+                } else if Path::new(hocr).exists() {
+                    fs::read_to_string(hocr)
+                        .at(hocr)
+                        .map(|s| confidence::parse_hocr(&s))
+                } else {
+                    fs::read_to_string(txt).at(txt).map(Recognition::plain)
+                }
+            }
+            None => Ok(parse_printed(&String::from_utf8_lossy(&out))),
+        },
+        Ok((status, _)) => Err(format!("{prog} exited with {status}").into()),
+        Err(e) => Err(e),
+    };
+    if !io.keep {
+        if let Some(img_in) = &io.img_in {
+            let _ = fs::remove_file(img_in);
+        }
+        for path in outputs.iter().flatten() {
+            let _ = fs::remove_file(path);
+        }
+    }
+    recognition
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut png = vec![];
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("could not encode cell image, {e}"))?;
+    Ok(png)
+}
+
+/// The image as a binary PPM, for engines reading only netpbm images (ocrad, gocr).
+fn encode_ppm(image: &RgbaImage) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", image.width(), image.height()).into_bytes();
+    for pixel in image.pixels() {
+        ppm.extend_from_slice(&pixel.0[..3]);
+    }
+    ppm
+}
+
+/// Splits a command into arguments as a shell would, on whitespace outside of
+/// quotes. Single quotes keep their content as is, while within double quotes and
+/// outside of quotes a backslash escapes the next character.
+fn split_command(cmd: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("unterminated quote in {cmd:?}").into()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => arg.extend(['\\', c]),
+                            None => return Err(format!("unterminated quote in {cmd:?}").into()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("unterminated quote in {cmd:?}").into()),
+                    }
+                }
+            }
+            '\\' => {
+                let c = chars
+                    .next()
+                    .ok_or_else(|| format!("trailing backslash in {cmd:?}"))?;
+                arg.get_or_insert_with(String::new).push(c);
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Splits a command into arguments, then fills in its placeholders, so that values
+/// with spaces stay one argument.
+fn expand_command(cmd: &str, placeholders: &[(&str, String)]) -> Result<Vec<String>> {
+    let args = split_command(cmd)?.into_iter().map(|arg| {
+        placeholders.iter().fold(arg, |arg, (placeholder, value)| {
+            arg.replace(placeholder, value)
+        })
+    });
+    Ok(args.collect())
+}
+
+/// Any engine run as a command, split into arguments as by a shell (quoting
+/// arguments with spaces), with placeholders for exchanging the cell image and
+/// text with it:
+///
+/// - `%img_in%`, path the cell image is saved to (as png),
+/// - `%txt_out%`, path (without extension) the text is read back from,
+/// - `%stdin%` and `%stdin_pnm%`, replaced by `-`, the cell image being piped to
+///   the engine as png or as a netpbm image,
+/// - `%stdout%`, replaced by `-`, the text being read from the engine's standard
+///   output, as it is whenever there is no `%txt_out%`,
+///
+/// and for passing settings and details of the cell, `%lang%`, `%psm%`, `%dpi%`,
+/// `%row%`, `%col%`, `%cell_w%` and `%cell_h%` (in pixels).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandBackend {
+    pub cmd_template: String,
+    pub lang: String,
+    /// Page segmentation mode, as of tesseract's `--psm`.
+    pub psm: u32,
+    /// Resolution of scanned images, for engines that cannot read it from the
+    /// image. PDF pages pass the resolution they were rendered at instead.
+    pub dpi: f32,
+}
+
+impl Default for CommandBackend {
+    fn default() -> Self {
+        Self {
+            cmd_template: COMMAND_PRESETS[0].1.to_string(),
+            lang: "eng".to_string(),
+            psm: 6,
+            dpi: pdf::DEFAULT_DPI,
+        }
+    }
+}
+
+impl OcrBackend for CommandBackend {
+    fn recognize(
+        &self,
+        image: &RgbaImage,
+        cell: &CellSpan,
+        run: &RunContext,
+    ) -> Result<Recognition> {
+        let (img_path, txt_out) = run.workspace.cell_paths(cell);
+        let uses = |placeholder: &str| self.cmd_template.contains(placeholder);
+        let stdin = if uses("%stdin_pnm%") {
+            Some(encode_ppm(image))
+        } else if uses("%stdin%") {
+            Some(encode_png(image)?)
+        } else {
+            None
+        };
+        let io = EngineIo {
+            // saved when kept, even if piped, to show what the engine was given
+            img_in: (uses("%img_in%") || run.workspace.keep).then(|| img_path.clone()),
+            stdin,
+            txt_out: (uses("%txt_out%") && !uses("%stdout%")).then(|| txt_out.clone()),
+            keep: run.workspace.keep,
+        };
+        let placeholders = [
+            ("%img_in%", img_path.clone()),
+            ("%txt_out%", txt_out.clone()),
+            ("%stdin_pnm%", "-".to_string()),
+            ("%stdin%", "-".to_string()),
+            ("%stdout%", "-".to_string()),
+            ("%lang%", self.lang.clone()),
+            ("%psm%", self.psm.to_string()),
+            ("%dpi%", format!("{:.0}", run.dpi.unwrap_or(self.dpi))),
+            ("%row%", cell.row.to_string()),
+            ("%col%", cell.col.to_string()),
+            ("%cell_w%", image.width().to_string()),
+            ("%cell_h%", image.height().to_string()),
+        ];
+        let args = expand_command(&self.cmd_template, &placeholders)?;
+        run_engine(args, image, io, run)
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tesseract {
+    pub lang: String,
+    /// Read word confidences from tesseract's TSV output.
+    pub confidence: bool,
+}
+
+impl OcrBackend for Tesseract {
+    fn recognize(
+        &self,
+        image: &RgbaImage,
+        cell: &CellSpan,
+        run: &RunContext,
+    ) -> Result<Recognition> {
+        let (img_path, txt_out) = run.workspace.cell_paths(cell);
+        let mut args = vec![
+            "tesseract".to_string(),
+            img_path.clone(),
+            txt_out.clone(),
+            "-l".to_string(),
+            self.lang.clone(),
+        ];
+        if self.confidence {
+            args.push("tsv".to_string());
+        }
+        let io = EngineIo {
+            img_in: Some(img_path),
+            stdin: None,
+            txt_out: Some(txt_out),
+            keep: run.workspace.keep,
+        };
+        run_engine(args, image, io, run)
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cuneiform {
+    pub lang: String,
+}
+
+impl OcrBackend for Cuneiform {
+    fn recognize(
+        &self,
+        image: &RgbaImage,
+        cell: &CellSpan,
+        run: &RunContext,
+    ) -> Result<Recognition> {
+        let (img_path, txt_out) = run.workspace.cell_paths(cell);
+        let args = vec![
+            "cuneiform".to_string(),
+            "-l".to_string(),
+            self.lang.clone(),
+            "-f".to_string(),
+            "text".to_string(),
+            "-o".to_string(),
+            format!("{txt_out}.txt"),
+            img_path.clone(),
+        ];
+        let io = EngineIo {
+            img_in: Some(img_path),
+            stdin: None,
+            txt_out: Some(txt_out),
+            keep: run.workspace.keep,
+        };
+        run_engine(args, image, io, run)
+    }
+}
+
+/// Reads `text` in every cell, or the cell's position when empty, for trying out
+/// a grid without an engine installed.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mock {
+    pub text: String,
+}
+
+impl OcrBackend for Mock {
+    fn recognize(
+        &self,
+        _image: &RgbaImage,
+        cell: &CellSpan,
+        _run: &RunContext,
+    ) -> Result<Recognition> {
+        let text = match self.text.is_empty() {
+            true => format!("{},{}", cell.row, cell.col),
+            false => self.text.clone(),
+        };
+        Ok(Recognition {
+            text,
+            confidence: Some(100.0),
+        })
+    }
+}
+
+/// Choice of OCR backend with its settings, as saved in templates.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendConfig {
+    Command(CommandBackend),
+    Tesseract(Tesseract),
+    Cuneiform(Cuneiform),
+    Mock(Mock),
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig::Tesseract(Tesseract {
+            lang: "eng".to_string(),
+            confidence: false,
+        })
+    }
+}
+
+/// Commands of common engines, for the command backend.
+pub const COMMAND_PRESETS: [(&str, &str); 6] = [
+    (
+        "Tesseract",
+        "tesseract -l %lang% --psm %psm% %img_in% %txt_out%",
+    ),
+    (
+        "Tesseract (confidence)",
+        "tesseract -l %lang% --psm %psm% %img_in% %txt_out% tsv",
+    ),
+    (
+        "Tesseract (piped)",
+        "tesseract %stdin% %stdout% -l %lang% --psm %psm% --dpi %dpi%",
+    ),
+    (
+        "Cuneiform",
+        "cuneiform -l %lang% -f text -o %txt_out%.txt %img_in%",
+    ),
+    ("Ocrad", "ocrad %stdin_pnm%"),
+    ("GOCR", "gocr -i %stdin_pnm%"),
+];
+
+impl BackendConfig {
+    pub fn command(cmd_template: impl Into<String>) -> Self {
+        BackendConfig::Command(CommandBackend {
+            cmd_template: cmd_template.into(),
+            ..Default::default()
+        })
+    }
+
+    pub fn backend(&self) -> &dyn OcrBackend {
+        match self {
+            BackendConfig::Command(b) => b,
+            BackendConfig::Tesseract(b) => b,
+            BackendConfig::Cuneiform(b) => b,
+            BackendConfig::Mock(b) => b,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            BackendConfig::Command(_) => "Command",
+            BackendConfig::Tesseract(_) => "Tesseract",
+            BackendConfig::Cuneiform(_) => "Cuneiform",
+            BackendConfig::Mock(_) => "Mock",
+        }
+    }
+
+    /// Each backend with its default settings.
+    fn defaults() -> [BackendConfig; 4] {
+        [
+            BackendConfig::command(COMMAND_PRESETS[0].1),
+            BackendConfig::default(),
+            BackendConfig::Cuneiform(Cuneiform {
+                lang: "eng".to_string(),
+            }),
+            BackendConfig::Mock(Mock {
+                text: String::new(),
+            }),
+        ]
+    }
+}
+
+/// Reads a backend, or the command of templates saved before backends could be
+/// chosen.
+pub fn deserialize_config<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<BackendConfig, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Saved {
+        Command(String),
+        Config(BackendConfig),
+    }
+    Ok(match Saved::deserialize(deserializer)? {
+        Saved::Command(cmd_template) => BackendConfig::command(cmd_template),
+        Saved::Config(config) => config,
+    })
+}
+
+fn lang_edit(ui: &mut egui::Ui, lang: &mut String) {
+    ui.label("Language");
+    ui.add(TextEdit::singleline(lang).desired_width(60.0));
+}
+
+impl EguiInspect for BackendConfig {
+    fn inspect(&self, label: &str, ui: &mut egui::Ui) {
+        ui.label(format!("{label}: {}", self.name()));
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ComboBox::new(ui.id().with(label), label)
+                .selected_text(self.name())
+                .show_ui(ui, |ui| {
+                    for config in BackendConfig::defaults() {
+                        let selected = config.name() == self.name();
+                        if ui.selectable_label(selected, config.name()).clicked() && !selected {
+                            *self = config;
+                        }
+                    }
+                });
+            match self {
+                BackendConfig::Command(b) => {
+                    ui.add(TextEdit::singleline(&mut b.cmd_template).desired_width(300.0));
+                    lang_edit(ui, &mut b.lang);
+                    ui.label("PSM");
+                    ui.add(DragValue::new(&mut b.psm).range(0..=13));
+                    ui.label("DPI");
+                    ui.add(DragValue::new(&mut b.dpi).range(50.0..=1200.0).speed(5.0));
+                    ui.menu_button("Preset commands", |ui| {
+                        for (name, cmd_template) in COMMAND_PRESETS {
+                            if ui.button(name).clicked() {
+                                b.cmd_template = cmd_template.to_string();
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                }
+                BackendConfig::Tesseract(b) => {
+                    lang_edit(ui, &mut b.lang);
+                    ui.checkbox(&mut b.confidence, "Confidence");
+                }
+                BackendConfig::Cuneiform(b) => lang_edit(ui, &mut b.lang),
+                BackendConfig::Mock(b) => {
+                    ui.label("Text");
+                    ui.add(TextEdit::singleline(&mut b.text).desired_width(100.0));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(cmd: &str) -> Vec<String> {
+        split_command(cmd).unwrap()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            split("  tesseract   a.png\tout "),
+            ["tesseract", "a.png", "out"]
+        );
+        assert!(split("").is_empty());
+    }
+
+    #[test]
+    fn splits_quotes() {
+        assert_eq!(
+            split(r#"ocr -c chars="0123456789 ." 'a b'c"#),
+            ["ocr", "-c", "chars=0123456789 .", "a bc"]
+        );
+        // single quotes keep backslashes, double quotes only escape `"` and `\`
+        assert_eq!(split(r#"'a\b' "c\"d\e\\""#), [r"a\b", r#"c"d\e\"#]);
+    }
+
+    #[test]
+    fn splits_escapes() {
+        assert_eq!(split(r"a\ b c\'d"), ["a b", "c'd"]);
+    }
+
+    #[test]
+    fn keeps_empty_arguments() {
+        assert_eq!(split(r#"ocr "" '' x"#), ["ocr", "", "", "x"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        for cmd in [r#"ocr "a b"#, "ocr 'a b", r#"ocr "a\"#, r"ocr a\"] {
+            assert!(split_command(cmd).is_err(), "{cmd}");
+        }
+    }
+
+    #[test]
+    fn fills_placeholders_after_splitting() {
+        let placeholders = [
+            ("%img_in%", "/tmp/my dir/crop_0_1.png".to_string()),
+            ("%stdin_pnm%", "-".to_string()),
+            ("%stdin%", "-".to_string()),
+            ("%lang%", "eng+deu".to_string()),
+        ];
+        assert_eq!(
+            expand_command("ocr %img_in% -l %lang% --x=%lang%", &placeholders).unwrap(),
+            [
+                "ocr",
+                "/tmp/my dir/crop_0_1.png",
+                "-l",
+                "eng+deu",
+                "--x=eng+deu"
+            ]
+        );
+        assert_eq!(
+            expand_command("ocrad %stdin_pnm% '%stdin%'", &placeholders).unwrap(),
+            ["ocrad", "-", "-"]
+        );
+    }
+}
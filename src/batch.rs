@@ -0,0 +1,406 @@
+//! Extraction of a recurring layout from many images, applying a single template.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use egui_inspect::{
+    background_task::{BackgroundTask, Task},
+    egui::{self, Color32, ColorImage, DragValue, Window},
+    EguiInspect,
+};
+use rayon::ThreadPool;
+
+use crate::{
+    backend::BackendConfig,
+    document::{self, PageRange},
+    error::{AtPath, Result},
+    export::{self, OutputFormat},
+    pdf,
+    template::Template,
+    BackgroundOCR, CellSpan, CellStatus, CleaningOptions, TableEdit, TableGrid,
+};
+
+/// Extensions of the images picked up from a batch directory.
+const IMAGE_EXTENSIONS: [&str; 9] = [
+    "png", "jpg", "jpeg", "tif", "tiff", "bmp", "gif", "webp", "pdf",
+];
+
+/// Images and PDFs of a directory, sorted by name.
+pub fn list_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images = vec![];
+    for entry in fs::read_dir(dir).at(dir)? {
+        let path = entry.at(dir)?.path();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if path.is_file() && IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Stem of the combined outputs of a batch.
+const COMBINED: &str = "combined";
+
+/// Stems (lowercased) shared by several images, or by an image and the combined
+/// outputs, whose outputs are told apart by the image's extension.
+fn clashing_stems(images: &[PathBuf]) -> HashSet<String> {
+    let mut seen = HashSet::from([COMBINED.to_string()]);
+    let mut clashing = HashSet::new();
+    for path in images {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        if !seen.insert(stem.to_lowercase()) {
+            clashing.insert(stem.to_lowercase());
+        }
+    }
+    clashing
+}
+
+/// How a template is applied to an image and its cells OCRed.
+#[derive(Clone)]
+pub struct Extraction {
+    pub template: Template,
+    pub backend: BackendConfig,
+    pub align: bool,
+    pub cleaning_options: CleaningOptions,
+    /// Keep the files exchanged with the OCR engine, for debugging it.
+    pub keep_files: bool,
+    /// Longest the OCR engine may take on a cell before it is killed.
+    pub timeout: Option<Duration>,
+    /// Set to stop the extraction, and a batch before its next image.
+    pub cancel: Arc<AtomicBool>,
+    /// Pool the cells are OCRed on, shared with other extractions, the global
+    /// pool when `None`.
+    pub pool: Option<Arc<ThreadPool>>,
+    /// Threads of each OCR engine process, the engine's default when `None`.
+    pub engine_threads: Option<usize>,
+}
+
+impl Extraction {
+    /// Extracts each of the template's grids from an image (a PDF page rendered at
+    /// `dpi`), as named tables, with the errors of the cells left empty.
+    pub fn run(
+        &self,
+        cim: ColorImage,
+        dpi: Option<f32>,
+    ) -> (Vec<(String, TableEdit)>, Vec<String>) {
+        let mut table_grid = TableGrid {
+            loaded_template: Some(self.template.clone()),
+            align_template: self.align,
+            ..Default::default()
+        };
+        table_grid.set_image(cim);
+
+        let cim = &table_grid.image.as_ref().unwrap().rectified;
+        let mut tables = vec![];
+        let mut errors = vec![];
+        for named in table_grid.grids.iter() {
+            let task = BackgroundOCR {
+                cleaning_options: self.cleaning_options,
+                keep_files: self.keep_files,
+                timeout: self.timeout,
+                cancel: self.cancel.clone(),
+                pool: self.pool.clone(),
+                engine_threads: self.engine_threads,
+                dpi,
+                ..BackgroundOCR::new(named.grid.clone(), cim.clone(), self.backend.clone())
+            };
+            let (table, cell_errors) = task.extract(|| {});
+            tables.push((named.name.clone(), table));
+            errors.extend(cell_errors.iter().map(|e| format!("{}: {e}", named.name)));
+        }
+        (tables, errors)
+    }
+}
+
+/// Stacks the tables extracted from several images, with a leading column naming
+/// the image (and page) each row came from.
+fn combine(tables: &[(&str, &TableEdit)]) -> TableEdit {
+    let mut items = vec![];
+    let mut status = vec![];
+    let mut confidence = vec![];
+    let mut spans = vec![];
+    for (source, table) in tables {
+        spans.extend(table.spans.iter().map(|span| CellSpan {
+            row: span.row + items.len(),
+            col: span.col + 1,
+            ..*span
+        }));
+        for row in table.items.iter() {
+            items.push([vec![source.to_string()], row.clone()].concat());
+        }
+        for row in table.status.iter() {
+            status.push([vec![CellStatus::Ok], row.clone()].concat());
+        }
+        for row in table.confidence.iter() {
+            confidence.push([vec![None], row.clone()].concat());
+        }
+    }
+    TableEdit {
+        items,
+        status,
+        confidence,
+        spans,
+        ..TableEdit::empty(0, 0)
+    }
+}
+
+pub struct BatchReport {
+    pub outputs: Vec<PathBuf>,
+    pub failures: Vec<(PathBuf, String)>,
+    /// Directories the files exchanged with the OCR engine were kept in.
+    pub kept_files: Vec<PathBuf>,
+}
+
+impl BatchReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} outputs written, {} failed",
+            self.outputs.len(),
+            self.failures.len()
+        );
+        for (path, e) in self.failures.iter() {
+            summary += &format!("\n  {}: {e}", path.display());
+        }
+        for dir in self.kept_files.iter() {
+            summary += &format!("\nintermediate files kept in {}", dir.display());
+        }
+        summary
+    }
+}
+
+/// Applies one extraction to each image (and each page in range of PDFs), writing
+/// an output per image and a combined one (per grid) to `out_dir`.
+pub struct Batch {
+    pub images: Vec<PathBuf>,
+    pub pages: PageRange,
+    pub dpi: f32,
+    pub out_dir: PathBuf,
+    pub format: OutputFormat,
+    pub extraction: Extraction,
+}
+
+impl Batch {
+    /// Runs the extraction image by image (the cells of each in parallel), calling
+    /// `on_image` as each is done. When cancelled, the pages left (and the one
+    /// being extracted) are reported as failed without writing their outputs, and
+    /// the combined outputs are written from those done.
+    pub fn run(&self, on_image: impl Fn()) -> BatchReport {
+        let ext = self.format.extension();
+        let mut report = BatchReport {
+            outputs: vec![],
+            failures: vec![],
+            kept_files: vec![],
+        };
+        let mut extracted = vec![];
+        let clashing = clashing_stems(&self.images);
+        let cancelled = || self.extraction.cancel.load(Ordering::Relaxed);
+        for path in self.images.iter() {
+            if cancelled() {
+                report
+                    .failures
+                    .push((path.clone(), "cancelled".to_string()));
+                continue;
+            }
+            let mut stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            // a.png and a.pdf would otherwise write the same outputs
+            if clashing.contains(&stem.to_lowercase()) {
+                let ext = path.extension().unwrap_or_default().to_string_lossy();
+                stem = format!("{stem}_{ext}").into();
+            }
+            match document::open_pages(path, self.pages, self.dpi) {
+                Ok(pages) => {
+                    for (page, cim) in pages {
+                        let (out, source) = match page {
+                            Some(page) => (format!("{stem}_p{page}"), format!("{name} p{page}")),
+                            None => (stem.to_string(), name.to_string()),
+                        };
+                        let out = self.out_dir.join(format!("{out}.{ext}"));
+                        if cancelled() {
+                            report.failures.push((out, "cancelled".to_string()));
+                            continue;
+                        }
+                        let dpi = pdf::is_pdf(path).then_some(self.dpi);
+                        let (tables, errors) = self.extraction.run(cim, dpi);
+                        // left incomplete
+                        if cancelled() {
+                            report.failures.push((out, "cancelled".to_string()));
+                            continue;
+                        }
+                        if !errors.is_empty() {
+                            report.failures.push((out.clone(), errors.join("; ")));
+                        }
+                        match export::write_tables(&tables, &out) {
+                            Ok(written) => report.outputs.extend(written),
+                            Err(e) => report.failures.push((out, e.to_string())),
+                        }
+                        let kept = tables.iter().filter_map(|(_, t)| t.kept_files.clone());
+                        report.kept_files.extend(kept);
+                        extracted.push((source, tables));
+                    }
+                }
+                Err(e) => report.failures.push((path.clone(), e.to_string())),
+            }
+            on_image();
+        }
+
+        let combined: Vec<(String, TableEdit)> = self
+            .extraction
+            .template
+            .grids
+            .iter()
+            .enumerate()
+            .map(|(k, tg)| {
+                let tables: Vec<_> = extracted
+                    .iter()
+                    .filter_map(|(source, tables)| Some((source.as_str(), &tables.get(k)?.1)))
+                    .collect();
+                (tg.name.clone(), combine(&tables))
+            })
+            .collect();
+        let out = self.out_dir.join(format!("{COMBINED}.{ext}"));
+        match export::write_tables(&combined, &out) {
+            Ok(written) => report.outputs.extend(written),
+            Err(e) => report.failures.push((out, e.to_string())),
+        }
+        report
+    }
+}
+
+#[derive(EguiInspect, Default)]
+pub struct BatchOCR {
+    #[inspect(hide)]
+    pub batch: Option<Batch>,
+    pub cleaning_options: CleaningOptions,
+}
+
+impl Task for BatchOCR {
+    type Return = BatchReport;
+
+    fn exec_with_expected_steps(&self) -> Option<usize> {
+        self.batch.as_ref().map(|batch| batch.images.len())
+    }
+
+    fn on_exec(&mut self, progress: egui_inspect::background_task::Progress) -> Self::Return {
+        let mut batch = self.batch.take().unwrap();
+        batch.extraction.cleaning_options = self.cleaning_options;
+        batch.run(|| progress.increment())
+    }
+}
+
+/// Window for choosing the directories of a batch and following its progress.
+pub struct BatchDialog {
+    pub open: bool,
+    dir: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    format: OutputFormat,
+    dpi: f32,
+    message: Option<String>,
+    task: BackgroundTask<BatchOCR>,
+    /// Cancel flag of the running batch.
+    cancel: Arc<AtomicBool>,
+}
+
+impl Default for BatchDialog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            dir: None,
+            out_dir: None,
+            format: Default::default(),
+            dpi: pdf::DEFAULT_DPI,
+            message: None,
+            task: Default::default(),
+            cancel: Default::default(),
+        }
+    }
+}
+
+fn pick_dir(ui: &mut egui::Ui, label: &str, dir: &mut Option<PathBuf>) {
+    if ui.button(label).clicked() {
+        if let Some(path) = rfd::FileDialog::new().set_directory(".").pick_folder() {
+            *dir = Some(path);
+        }
+    }
+    match dir {
+        Some(path) => ui.label(path.display().to_string()),
+        None => ui.weak("(none)"),
+    };
+}
+
+impl BatchDialog {
+    /// Shows the dialog, `extraction` giving the template and command to apply
+    /// when a batch is started.
+    pub fn show(&mut self, ctx: &egui::Context, extraction: impl FnOnce() -> Option<Extraction>) {
+        let mut open = self.open;
+        Window::new("Batch").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| pick_dir(ui, "Image directory", &mut self.dir));
+            ui.horizontal(|ui| pick_dir(ui, "Output directory", &mut self.out_dir));
+            ui.horizontal(|ui| {
+                ui.label("Format");
+                for format in [OutputFormat::Csv, OutputFormat::Html, OutputFormat::Xlsx] {
+                    ui.selectable_value(&mut self.format, format, format.extension());
+                }
+                ui.label("PDF DPI");
+                ui.add(DragValue::new(&mut self.dpi).range(50.0..=600.0).speed(5.0));
+            });
+
+            self.task.inspect_mut("", ui);
+            let ongoing = matches!(self.task, BackgroundTask::Ongoing { .. });
+            if !ongoing && ui.button("Run batch").clicked() {
+                self.message = self.start(extraction()).err();
+            }
+            if ongoing && ui.button("Cancel").clicked() {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            if let BackgroundTask::Finished {
+                result: Ok(report), ..
+            } = &self.task
+            {
+                ui.label(report.summary());
+            }
+            if let Some(message) = &self.message {
+                ui.colored_label(Color32::RED, message.as_str());
+            }
+        });
+        self.open = open;
+    }
+
+    fn start(&mut self, extraction: Option<Extraction>) -> std::result::Result<(), String> {
+        let mut extraction = extraction.ok_or("Load an image or template first")?;
+        let (Some(dir), Some(out_dir)) = (&self.dir, &self.out_dir) else {
+            return Err("Pick the image and output directories first".to_string());
+        };
+        let images = list_images(dir).map_err(|e| e.to_string())?;
+        if images.is_empty() {
+            return Err(format!("No images found in {}", dir.display()));
+        }
+        if let BackgroundTask::Starting { task } | BackgroundTask::Finished { task, .. } =
+            &mut self.task
+        {
+            self.cancel = Default::default();
+            extraction.cancel = self.cancel.clone();
+            *task = BatchOCR {
+                batch: Some(Batch {
+                    images,
+                    pages: Default::default(),
+                    dpi: self.dpi,
+                    out_dir: out_dir.clone(),
+                    format: self.format,
+                    extraction,
+                }),
+                cleaning_options: task.cleaning_options,
+            };
+        }
+        Ok(())
+    }
+}
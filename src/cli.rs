@@ -0,0 +1,226 @@
+//! Headless command line extraction, for running without a display.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    backend::{self, BackendConfig, DEFAULT_CELL_TIMEOUT, DEFAULT_ENGINE_THREADS},
+    batch::{list_images, Batch, Extraction},
+    continued,
+    document::{self, PageRange},
+    error::{AtPath, Result},
+    export::{self, OutputFormat},
+    pdf,
+    template::Template,
+    worker_pool, OCROptions, TableEdit,
+};
+
+/// Table OCR, starts the annotation window when no command is given.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// Extract tables from an image with a grid template saved from the annotation window.
+    Extract(ExtractArgs),
+    /// Extract tables from every image of a directory with the same grid template.
+    Batch(BatchArgs),
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    /// Table image, PDF or multi-page TIFF.
+    #[arg(long)]
+    image: PathBuf,
+    /// Output file, written as csv, html or xlsx by extension. With several grids,
+    /// csv and html outputs are written per grid, suffixed by the grid's name, and
+    /// with several pages per page, suffixed by its number, unless continuing
+    /// tables.
+    #[arg(long)]
+    out: PathBuf,
+    #[command(flatten)]
+    template: TemplateArgs,
+    #[command(flatten)]
+    run: RunArgs,
+    /// Concatenate each grid's tables over the pages into a single output.
+    #[arg(long)]
+    continue_table: bool,
+    /// Header rows of continued tables, dropped where repeated on later pages.
+    #[arg(long, default_value_t = 1, requires = "continue_table")]
+    header_rows: usize,
+}
+
+#[derive(Args)]
+pub struct BatchArgs {
+    /// Directory of table images and PDFs.
+    #[arg(long)]
+    dir: PathBuf,
+    /// Output directory, receiving a file per image and the combined tables.
+    #[arg(long)]
+    out_dir: PathBuf,
+    #[arg(long, value_enum, default_value = "csv")]
+    format: OutputFormat,
+    #[command(flatten)]
+    template: TemplateArgs,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// Pages read from each document, and the template and backend applied to them.
+#[derive(Args)]
+pub struct TemplateArgs {
+    /// Pages of PDFs and multi-page TIFFs to extract, e.g. 3, 2-5 or 2- (all by
+    /// default).
+    #[arg(long, default_value = "1-")]
+    pages: PageRange,
+    /// Resolution PDF pages are rasterized at.
+    #[arg(long, default_value_t = pdf::DEFAULT_DPI)]
+    dpi: f32,
+    /// Grid template (json).
+    #[arg(long)]
+    grid: PathBuf,
+    /// OCR backend preset, replacing the template's backend.
+    #[arg(long, value_enum)]
+    backend: Option<OCROptions>,
+    /// OCR command, replacing the template's backend, with `%img_in%` replaced by
+    /// the cell image and `%txt_out%` by the output path (without extension), or
+    /// `%stdin%`/`%stdin_pnm%` and `%stdout%` piping the image and text instead.
+    /// Arguments are quoted as in a shell.
+    #[arg(long, conflicts_with = "backend")]
+    cmd: Option<String>,
+    /// Do not register the template onto each image.
+    #[arg(long)]
+    no_align: bool,
+}
+
+/// How the OCR engine is run on the cells.
+#[derive(Args)]
+pub struct RunArgs {
+    /// Keep the files exchanged with the OCR engine, for debugging it.
+    #[arg(long)]
+    keep_files: bool,
+    /// Seconds the OCR engine may take on a cell before it is killed and the cell
+    /// failed, 0 for no limit.
+    #[arg(long, default_value_t = DEFAULT_CELL_TIMEOUT)]
+    timeout: f32,
+    /// Cells OCRed at once, 0 for one per core.
+    #[arg(long, default_value_t = 0)]
+    workers: usize,
+    /// Threads of each OCR engine process (`OMP_THREAD_LIMIT`), 0 for the
+    /// engine's default.
+    #[arg(long, default_value_t = DEFAULT_ENGINE_THREADS)]
+    engine_threads: usize,
+}
+
+pub fn run(command: CliCommand) -> Result<()> {
+    match command {
+        CliCommand::Extract(args) => extract(args),
+        CliCommand::Batch(args) => batch(args),
+    }
+}
+
+/// Template, backend and cleaning of an extraction from the command line.
+fn extraction(args: &TemplateArgs, run: &RunArgs) -> Result<Extraction> {
+    let template = Template::load(&args.grid)?;
+    let backend = match (args.backend, &args.cmd) {
+        (_, Some(cmd)) => BackendConfig::command(cmd.clone()),
+        (Some(backend), None) => backend.backend(),
+        (None, None) => template.backend.clone(),
+    };
+    Ok(Extraction {
+        template,
+        backend,
+        align: !args.no_align,
+        cleaning_options: Default::default(),
+        keep_files: run.keep_files,
+        timeout: backend::timeout(run.timeout),
+        cancel: Default::default(),
+        pool: Some(worker_pool(run.workers)?),
+        engine_threads: backend::engine_threads(run.engine_threads),
+    })
+}
+
+/// Prints the errors of cells left empty and where intermediate files were kept,
+/// keeping the tables.
+fn warn((tables, errors): (Vec<(String, TableEdit)>, Vec<String>)) -> Vec<(String, TableEdit)> {
+    for e in errors {
+        eprintln!("warning: {e}");
+    }
+    for dir in tables.iter().filter_map(|(_, t)| t.kept_files.as_ref()) {
+        eprintln!("intermediate files kept in {}", dir.display());
+    }
+    tables
+}
+
+fn extract(args: ExtractArgs) -> Result<()> {
+    let extraction = extraction(&args.template, &args.run)?;
+    let pages = document::open_pages(&args.image, args.template.pages, args.template.dpi)?;
+    let dpi = pdf::is_pdf(&args.image).then_some(args.template.dpi);
+    if args.continue_table {
+        let page_tables: Vec<_> = pages
+            .into_iter()
+            .map(|(_, cim)| warn(extraction.run(cim, dpi)))
+            .collect();
+        let tables: Vec<_> = extraction
+            .template
+            .grids
+            .iter()
+            .enumerate()
+            .map(|(k, tg)| {
+                let tables: Vec<_> = page_tables
+                    .iter()
+                    .filter_map(|t| Some(&t.get(k)?.1))
+                    .collect();
+                (
+                    tg.name.clone(),
+                    continued::concat(&tables, args.header_rows),
+                )
+            })
+            .collect();
+        return export::write_tables(&tables, &args.out).map(|_| ());
+    }
+    let single = pages.len() == 1;
+    for (page, cim) in pages {
+        let out = match (page, single) {
+            (Some(page), false) => export::with_suffix(&args.out, &format!("p{page}")),
+            _ => args.out.clone(),
+        };
+        export::write_tables(&warn(extraction.run(cim, dpi)), &out)?;
+    }
+    Ok(())
+}
+
+fn batch(args: BatchArgs) -> Result<()> {
+    let images = list_images(&args.dir)?;
+    if images.is_empty() {
+        return Err(format!("no images found in {}", args.dir.display()).into());
+    }
+    fs::create_dir_all(&args.out_dir).at(&args.out_dir)?;
+    let batch = Batch {
+        images,
+        pages: args.template.pages,
+        dpi: args.template.dpi,
+        out_dir: args.out_dir,
+        format: args.format,
+        extraction: extraction(&args.template, &args.run)?,
+    };
+    let done = AtomicUsize::new(0);
+    let report = batch.run(|| {
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        eprintln!("{done}/{}", batch.images.len());
+    });
+    eprintln!("{}", report.summary());
+    match report.failures.is_empty() {
+        true => Ok(()),
+        false => Err(format!("batch finished with {} failure(s)", report.failures.len()).into()),
+    }
+}
@@ -0,0 +1,205 @@
+//! Text and word confidences read from backends that report them, tesseract's
+//! TSV and hOCR outputs.
+
+use iter_tools::Itertools;
+
+use crate::backend::Recognition;
+
+/// Confidence (in percent) below which a cell is taken as likely misread.
+pub const LOW_CONFIDENCE: f32 = 60.0;
+
+struct Word {
+    text: String,
+    confidence: f32,
+}
+
+/// Words of a cell with their confidences, grouped into lines.
+#[derive(Default)]
+struct Words {
+    lines: Vec<Vec<Word>>,
+}
+
+impl Words {
+    fn push(&mut self, text: &str, confidence: f32) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let word = Word {
+            text: text.to_string(),
+            confidence,
+        };
+        match self.lines.last_mut() {
+            Some(line) => line.push(word),
+            None => self.lines.push(vec![word]),
+        }
+    }
+
+    fn new_line(&mut self) {
+        if self.lines.last().map_or(true, |line| !line.is_empty()) {
+            self.lines.push(vec![]);
+        }
+    }
+
+    /// The text, words separated by spaces and lines by newlines, with the mean
+    /// confidence of its words.
+    fn finish(self) -> Recognition {
+        let text = self
+            .lines
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.iter().map(|w| w.text.as_str()).join(" "))
+            .join("\n");
+        let confidences: Vec<f32> = self.lines.iter().flatten().map(|w| w.confidence).collect();
+        let confidence = match confidences.len() {
+            0 => None,
+            n => Some(confidences.iter().sum::<f32>() / n as f32),
+        };
+        Recognition { text, confidence }
+    }
+}
+
+/// Parses tesseract's TSV output, one row per page, block, paragraph, line and
+/// word, of which only the words carry a confidence.
+pub fn parse_tsv(tsv: &str) -> Recognition {
+    let mut words = Words::default();
+    let mut line_key = None;
+    for row in tsv.lines().skip(1) {
+        let fields: Vec<&str> = row.split('\t').collect();
+        let [level, _page, block, par, line, _word, _left, _top, _width, _height, conf, text] =
+            fields.as_slice()
+        else {
+            continue;
+        };
+        let (Ok(5), Ok(conf)) = (level.parse::<u32>(), conf.parse::<f32>()) else {
+            continue;
+        };
+        if conf < 0.0 {
+            continue;
+        }
+        let key = (*block, *par, *line);
+        if line_key != Some(key) {
+            words.new_line();
+            line_key = Some(key);
+        }
+        words.push(text, conf);
+    }
+    words.finish()
+}
+
+fn unescape_html(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Text content of markup, with its tags removed.
+fn strip_tags(s: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    unescape_html(&out)
+}
+
+/// Parses tesseract's hOCR output, reading the `x_wconf` of each `ocrx_word` span
+/// and starting a new line at each line level span (`ocr_line`, `ocr_header`...).
+pub fn parse_hocr(hocr: &str) -> Recognition {
+    let mut words = Words::default();
+    let hocr = hocr.replace('"', "'");
+    for chunk in hocr.split("<span class='ocr").skip(1) {
+        let Some(word) = chunk.strip_prefix("x_word'") else {
+            words.new_line();
+            continue;
+        };
+        let Some((tag, content)) = word.split_once('>') else {
+            continue;
+        };
+        let title = tag.split("title='").nth(1).unwrap_or("");
+        let title = title.split('\'').next().unwrap_or("");
+        let property = |name: &str| {
+            title
+                .split(';')
+                .find_map(|prop| prop.trim().strip_prefix(name))
+                .map(str::trim)
+        };
+        let confidence = property("x_wconf ").and_then(|conf| conf.parse::<f32>().ok());
+        let content = content.split("</span>").next().unwrap_or("");
+        if let Some(confidence) = confidence {
+            words.push(&strip_tags(content), confidence);
+        }
+    }
+    words.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `tesseract cell.png - tsv` on a two line cell
+    const TSV: &str = concat!(
+        "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\t",
+        "left\ttop\twidth\theight\tconf\ttext\n",
+        "1\t1\t0\t0\t0\t0\t0\t0\t200\t60\t-1\t\n",
+        "2\t1\t1\t0\t0\t0\t4\t6\t150\t48\t-1\t\n",
+        "3\t1\t1\t1\t0\t0\t4\t6\t150\t48\t-1\t\n",
+        "4\t1\t1\t1\t1\t0\t4\t6\t116\t18\t-1\t\n",
+        "5\t1\t1\t1\t1\t1\t4\t6\t56\t18\t96.5\tTotal\n",
+        "5\t1\t1\t1\t1\t2\t70\t6\t50\t18\t91\tR&D\n",
+        "4\t1\t1\t1\t2\t0\t4\t34\t40\t20\t-1\t\n",
+        "5\t1\t1\t1\t2\t1\t4\t34\t40\t20\t80\t42\n",
+        "5\t1\t1\t1\t2\t2\t50\t34\t10\t20\t-1\t \n",
+    );
+
+    // `tesseract cell.png - hocr` on the same cell, trimmed to the page
+    const HOCR: &str = r#"
+  <div class='ocr_page' id='page_1' title='image "cell.png"; bbox 0 0 200 60; ppageno 0'>
+   <div class='ocr_carea' id='block_1_1' title="bbox 4 6 154 54">
+    <p class='ocr_par' id='par_1_1' lang='eng' title="bbox 4 6 154 54">
+     <span class='ocr_line' id='line_1_1' title="bbox 4 6 120 24; baseline 0 -4; x_size 18; x_descenders 4; x_ascenders 4">
+      <span class='ocrx_word' id='word_1_1' title='bbox 4 6 60 24; x_wconf 96'>Total</span>
+      <span class='ocrx_word' id='word_1_2' title='bbox 70 6 120 24; x_wconf 91'><strong>R&amp;D</strong></span>
+     </span>
+     <span class='ocr_header' id='line_1_2' title="bbox 4 34 44 54; baseline 0 -3; x_size 20; x_descenders 4; x_ascenders 5">
+      <span class='ocrx_word' id='word_1_3' title='bbox 4 34 44 54; x_wconf 80'>42</span>
+     </span>
+    </p>
+   </div>
+  </div>
+"#;
+
+    #[test]
+    fn reads_tsv_words_by_line() {
+        let recognition = parse_tsv(TSV);
+        assert_eq!(recognition.text, "Total R&D\n42");
+        assert_eq!(recognition.confidence, Some((96.5 + 91.0 + 80.0) / 3.0));
+    }
+
+    #[test]
+    fn skips_tsv_rows_without_confidence() {
+        let tsv = concat!(
+            "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\t",
+            "left\ttop\twidth\theight\tconf\ttext\n",
+            "5\t1\t1\t1\t1\t1\t4\t6\t56\t18\t-1\tghost\n",
+        );
+        let recognition = parse_tsv(tsv);
+        assert_eq!(recognition.text, "");
+        assert_eq!(recognition.confidence, None);
+    }
+
+    #[test]
+    fn reads_hocr_words_by_line() {
+        let recognition = parse_hocr(HOCR);
+        assert_eq!(recognition.text, "Total R&D\n42");
+        assert_eq!(recognition.confidence, Some((96.0 + 91.0 + 80.0) / 3.0));
+    }
+}
@@ -0,0 +1,139 @@
+//! Tables continuing over several pages, concatenated into one logical table.
+
+use crate::{CellSpan, CellStatus, TableEdit};
+
+/// Similarity above which an OCRed cell is taken as a repeat of the header cell,
+/// tolerating recognition noise.
+const HEADER_SIMILARITY: f64 = 0.8;
+
+/// Lowercase alphanumerics of a cell, ignoring spacing and punctuation.
+fn normalize(s: &str) -> Vec<char> {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + (ca != cb) as usize)
+                .min(prev[j + 1] + 1)
+                .min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    match a.len().max(b.len()) {
+        0 => 1.0,
+        len => 1.0 - levenshtein(&a, &b) as f64 / len as f64,
+    }
+}
+
+/// Whether `row` repeats the `header` row, cell by cell.
+fn is_repeated_header(header: &[String], row: &[String]) -> bool {
+    header.len() == row.len()
+        && header.iter().any(|h| !normalize(h).is_empty())
+        && header
+            .iter()
+            .zip(row.iter())
+            .all(|(h, r)| similarity(h, r) >= HEADER_SIMILARITY)
+}
+
+/// Appends the tables in order, dropping the leading rows of each continuation
+/// that repeat the first `header_rows` rows of the first table. Rows of narrower
+/// tables (a grid that gained a column on a later page) are padded with empty
+/// cells to the widest.
+pub fn concat(tables: &[&TableEdit], header_rows: usize) -> TableEdit {
+    let mut out = TableEdit::empty(0, 0);
+    let Some(first) = tables.first() else {
+        return out;
+    };
+    let header = &first.items[..header_rows.min(first.items.len())];
+    for (k, table) in tables.iter().enumerate() {
+        let skip = match k {
+            0 => 0,
+            _ => header
+                .iter()
+                .zip(table.items.iter())
+                .take_while(|(h, row)| is_repeated_header(h, row))
+                .count(),
+        };
+        let offset = out.items.len();
+        out.spans.extend(
+            table
+                .spans
+                .iter()
+                .filter(|span| span.row >= skip)
+                .map(|span| CellSpan {
+                    row: span.row - skip + offset,
+                    ..*span
+                }),
+        );
+        out.items.extend(table.items.iter().skip(skip).cloned());
+        out.status.extend(table.status.iter().skip(skip).cloned());
+        out.confidence
+            .extend(table.confidence.iter().skip(skip).cloned());
+    }
+
+    let ncols = out.items.iter().map(Vec::len).max().unwrap_or(0);
+    for i in 0..out.items.len() {
+        out.items[i].resize(ncols, String::new());
+        out.status[i].resize(ncols, CellStatus::Empty);
+        out.confidence[i].resize(ncols, None);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: &[&[&str]]) -> TableEdit {
+        TableEdit {
+            items: rows
+                .iter()
+                .map(|row| row.iter().map(|s| s.to_string()).collect())
+                .collect(),
+            status: rows
+                .iter()
+                .map(|row| vec![CellStatus::Ok; row.len()])
+                .collect(),
+            confidence: rows.iter().map(|row| vec![None; row.len()]).collect(),
+            ..TableEdit::empty(0, 0)
+        }
+    }
+
+    #[test]
+    fn pads_narrower_tables() {
+        let first = table(&[&["Name", "Value"], &["a", "1"]]);
+        let second = table(&[&["b", "2", "x"]]);
+        let out = concat(&[&first, &second], 1);
+        assert_eq!(out.items.len(), 3);
+        for i in 0..3 {
+            assert_eq!(out.items[i].len(), 3);
+            assert_eq!(out.status[i].len(), 3);
+            assert_eq!(out.confidence[i].len(), 3);
+        }
+        assert_eq!(out.items[1], ["a", "1", ""]);
+        assert!(out.status[1][2] == CellStatus::Empty);
+        assert_eq!(out.items[2], ["b", "2", "x"]);
+    }
+
+    #[test]
+    fn drops_repeated_headers() {
+        let first = table(&[&["Name", "Value"], &["a", "1"]]);
+        // misread repeat of the header, then a row that only resembles it
+        let second = table(&[&["Name:", "Vaiue"], &["b", "2"]]);
+        let third = table(&[&["Names", "Total"], &["c", "3"]]);
+        let out = concat(&[&first, &second, &third], 1);
+        let rows: Vec<_> = out.items.iter().map(|row| row[0].as_str()).collect();
+        assert_eq!(rows, ["Name", "a", "b", "Names", "c"]);
+    }
+}
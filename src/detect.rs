@@ -0,0 +1,320 @@
+//! Image analysis used to seed the grid from the table image.
+//!
+//! All positions returned are in the normalized plot coordinates used by the
+//! separators, x in [0, 1] left to right and y in [0, 1] bottom to top.
+
+use egui_inspect::egui::ColorImage;
+use image::{GrayImage, Luma};
+use imageproc::contrast::otsu_level;
+
+/// Dark (ink) pixels of an image, thresholded with Otsu's method.
+pub struct InkMask {
+    pub width: usize,
+    pub height: usize,
+    ink: Vec<bool>,
+}
+
+impl InkMask {
+    pub fn new(cim: &ColorImage) -> Self {
+        let [width, height] = cim.size;
+        let gray = GrayImage::from_fn(width as u32, height as u32, |i, j| {
+            let color = cim.pixels[(j as usize) * width + (i as usize)];
+            // transparent padding (e.g. from rotation) counts as background
+            if color.a() == 0 {
+                return Luma([255]);
+            }
+            let l = 0.299 * color.r() as f32 + 0.587 * color.g() as f32 + 0.114 * color.b() as f32;
+            Luma([l as u8])
+        });
+        let level = otsu_level(&gray);
+        let ink = gray.pixels().map(|p| p.0[0] < level).collect();
+        Self { width, height, ink }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> bool {
+        self.ink[j * self.width + i]
+    }
+
+    fn row(&self, j: usize) -> impl Iterator<Item = bool> + '_ {
+        self.ink[j * self.width..(j + 1) * self.width].iter().copied()
+    }
+
+    fn col(&self, i: usize) -> impl Iterator<Item = bool> + '_ {
+        (0..self.height).map(move |j| self.get(i, j))
+    }
+}
+
+pub fn norm_x(i: f64, width: usize) -> f64 {
+    (i + 0.5) / (width as f64)
+}
+
+pub fn norm_y(j: f64, height: usize) -> f64 {
+    1.0 - (j + 0.5) / (height as f64)
+}
+
+#[derive(Clone, Copy)]
+pub struct LineDetection {
+    /// Minimum length of a ruling line, as a fraction of the image width/height.
+    pub min_length: f64,
+    /// Largest break (in pixels) tolerated within a single ruling line.
+    pub max_gap: usize,
+}
+
+impl Default for LineDetection {
+    fn default() -> Self {
+        Self {
+            min_length: 0.5,
+            max_gap: 2,
+        }
+    }
+}
+
+/// Longest run of ink, bridging breaks of up to `max_gap` pixels.
+fn longest_run(ink: impl Iterator<Item = bool>, max_gap: usize) -> usize {
+    let (mut best, mut run, mut gap) = (0, 0, 0);
+    for p in ink {
+        if p {
+            run += gap + 1;
+            gap = 0;
+            best = best.max(run);
+        } else if run > 0 {
+            gap += 1;
+            if gap > max_gap {
+                run = 0;
+                gap = 0;
+            }
+        }
+    }
+    best
+}
+
+/// Centers (in pixels) of each run of consecutive set flags.
+fn run_centers(flags: &[bool]) -> Vec<f64> {
+    let mut centers = vec![];
+    let mut start = None;
+    for (k, &f) in flags.iter().chain([false].iter()).enumerate() {
+        match (f, start) {
+            (true, None) => start = Some(k),
+            (false, Some(s)) => {
+                centers.push((s + k - 1) as f64 / 2.0);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    centers
+}
+
+/// Finds long horizontal and vertical strokes, i.e. the ruling lines of a bordered
+/// table. Each row (column) whose longest ink run spans `min_length` of the image
+/// is a line pixel row (column), adjacent ones are merged into a single line.
+///
+/// Returns the (horizontal y, vertical x) positions.
+pub fn ruling_lines(mask: &InkMask, opts: LineDetection) -> (Vec<f64>, Vec<f64>) {
+    let min_w = (opts.min_length * mask.width as f64) as usize;
+    let min_h = (opts.min_length * mask.height as f64) as usize;
+
+    let horiz_flags: Vec<bool> = (0..mask.height)
+        .map(|j| longest_run(mask.row(j), opts.max_gap) >= min_w)
+        .collect();
+    let vert_flags: Vec<bool> = (0..mask.width)
+        .map(|i| longest_run(mask.col(i), opts.max_gap) >= min_h)
+        .collect();
+
+    let ys = run_centers(&horiz_flags)
+        .into_iter()
+        .map(|j| norm_y(j, mask.height))
+        .collect();
+    let xs = run_centers(&vert_flags)
+        .into_iter()
+        .map(|i| norm_x(i, mask.width))
+        .collect();
+    (ys, xs)
+}
+
+/// Fraction of ink pixels below which a pixel row/column still counts as blank,
+/// tolerating specks and the outer ruling lines of the selected region.
+const BLANK_FRACTION: f64 = 0.02;
+
+fn px(t: f64, len: usize) -> usize {
+    ((t.clamp(0.0, 1.0) * len as f64) as usize).min(len)
+}
+
+/// Centers (in pixels) of interior blank runs, those not touching either end of
+/// `blank`, keeping only runs at least `1 - sensitivity` as wide as the widest.
+fn gap_centers(blank: &[bool], sensitivity: f64) -> Vec<f64> {
+    let mut runs = vec![];
+    let mut start = None;
+    for (k, &b) in blank.iter().enumerate() {
+        match (b, start) {
+            (true, None) => start = Some(k),
+            (false, Some(s)) => {
+                if s > 0 {
+                    runs.push((s, k));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    let widest = runs.iter().map(|(s, e)| e - s).max().unwrap_or(0);
+    let min_width = ((1.0 - sensitivity) * widest as f64).max(1.0);
+    runs.into_iter()
+        .filter(|(s, e)| (e - s) as f64 >= min_width)
+        .map(|(s, e)| (s + e - 1) as f64 / 2.0)
+        .collect()
+}
+
+/// Projection profile analysis for borderless tables, finds the whitespace between
+/// text columns and text lines within the given region (`xmin, xmax, ymin, ymax` in
+/// normalized coordinates). A higher `sensitivity` (in [0, 1]) also accepts
+/// narrower gaps.
+///
+/// Returns the interior (horizontal y, vertical x) separator positions.
+pub fn whitespace_gaps(
+    mask: &InkMask,
+    [xmin, xmax, ymin, ymax]: [f64; 4],
+    sensitivity: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let (i0, i1) = (px(xmin, mask.width), px(xmax, mask.width));
+    let (j0, j1) = (px(1.0 - ymax, mask.height), px(1.0 - ymin, mask.height));
+    if i1 <= i0 || j1 <= j0 {
+        return (vec![], vec![]);
+    }
+
+    let mut row_ink = vec![0; j1 - j0];
+    let mut col_ink = vec![0; i1 - i0];
+    for j in j0..j1 {
+        for i in i0..i1 {
+            if mask.get(i, j) {
+                row_ink[j - j0] += 1;
+                col_ink[i - i0] += 1;
+            }
+        }
+    }
+    let blank = |ink: Vec<usize>, span: usize| -> Vec<bool> {
+        ink.into_iter()
+            .map(|n| (n as f64) <= BLANK_FRACTION * span as f64)
+            .collect()
+    };
+
+    let ys = gap_centers(&blank(row_ink, i1 - i0), sensitivity)
+        .into_iter()
+        .map(|j| norm_y(j + j0 as f64, mask.height))
+        .collect();
+    let xs = gap_centers(&blank(col_ink, j1 - j0), sensitivity)
+        .into_iter()
+        .map(|i| norm_x(i + i0 as f64, mask.width))
+        .collect();
+    (ys, xs)
+}
+
+/// Upper bound on ink pixels considered when estimating skew, larger images are
+/// subsampled.
+const SKEW_MAX_SAMPLES: usize = 100_000;
+
+/// Sharpness of the projection profile of `points` onto the normal of a line at
+/// `angle` (sum of squared bin counts, maximal when text lines/rules are aligned).
+fn profile_sharpness(points: &[(f32, f32)], angle: f32) -> f64 {
+    let (s, c) = angle.sin_cos();
+    let mut bins = std::collections::HashMap::<i32, u32>::new();
+    for &(x, y) in points {
+        *bins.entry((-x * s + y * c).round() as i32).or_default() += 1;
+    }
+    bins.values().map(|&n| (n as f64).powi(2)).sum()
+}
+
+/// Estimates the skew of text baselines and ruling lines by maximizing the
+/// variance of the projection profile over angles in `[-max_angle, max_angle]`,
+/// with a coarse then fine search.
+///
+/// Returns the clockwise rotation (as used by `rotate_about_center`) levelling
+/// the image.
+pub fn estimate_skew(mask: &InkMask, max_angle: f32) -> f32 {
+    let stride = (mask.ink.iter().filter(|&&p| p).count() / SKEW_MAX_SAMPLES).max(1);
+    let points: Vec<(f32, f32)> = mask
+        .ink
+        .iter()
+        .enumerate()
+        .filter(|(_, &p)| p)
+        .step_by(stride)
+        .map(|(k, _)| ((k % mask.width) as f32, (k / mask.width) as f32))
+        .collect();
+    if points.is_empty() {
+        return 0.0;
+    }
+
+    let best_in = |from: f32, to: f32, step: f32| {
+        let n = ((to - from) / step).round() as i32;
+        (0..=n)
+            .map(|k| from + k as f32 * step)
+            .map(|a| (a, profile_sharpness(&points, a)))
+            .max_by(|(_, v1), (_, v2)| v1.total_cmp(v2))
+            .map(|(a, _)| a)
+            .unwrap_or(0.0)
+    };
+    let coarse_step = 0.5f32.to_radians();
+    let coarse = best_in(-max_angle, max_angle, coarse_step);
+    let fine = best_in(
+        coarse - coarse_step,
+        coarse + coarse_step,
+        0.02f32.to_radians(),
+    );
+
+    -fine.clamp(-max_angle, max_angle)
+}
+
+/// Ink per column and per row band, with `bins` bands spanning the image in each
+/// direction (rows from the bottom, as the normalized y).
+pub fn ink_profiles(mask: &InkMask, bins: usize) -> (Vec<f32>, Vec<f32>) {
+    let mut cols = vec![0.0; bins];
+    let mut rows = vec![0.0; bins];
+    for j in 0..mask.height {
+        for i in 0..mask.width {
+            if mask.get(i, j) {
+                cols[i * bins / mask.width] += 1.0;
+                rows[(mask.height - 1 - j) * bins / mask.height] += 1.0;
+            }
+        }
+    }
+    (cols, rows)
+}
+
+/// Normalized cross correlation of `reference` with `profile` sampled at
+/// `scale * x + shift` for each reference position `x`.
+fn correlation(reference: &[f32], profile: &[f32], scale: f64, shift: f64) -> f64 {
+    let n = reference.len();
+    let sampled: Vec<f64> = (0..n)
+        .map(|k| {
+            let x = scale * (k as f64 + 0.5) / n as f64 + shift;
+            match (0.0..1.0).contains(&x) {
+                true => profile[(x * profile.len() as f64) as usize] as f64,
+                false => 0.0,
+            }
+        })
+        .collect();
+    let mean_r = reference.iter().map(|&r| r as f64).sum::<f64>() / n as f64;
+    let mean_s = sampled.iter().sum::<f64>() / n as f64;
+    let (mut cov, mut var_r, mut var_s) = (0.0, 0.0, 0.0);
+    for (&r, &s) in reference.iter().zip(sampled.iter()) {
+        let (dr, ds) = (r as f64 - mean_r, s - mean_s);
+        cov += dr * ds;
+        var_r += dr * dr;
+        var_s += ds * ds;
+    }
+    match var_r * var_s > 0.0 {
+        true => cov / (var_r * var_s).sqrt(),
+        false => 0.0,
+    }
+}
+
+/// Scale and shift `(s, t)` registering a reference ink profile onto that of
+/// another image, such that reference position `x` lands on `s * x + t`.
+pub fn align_profiles(reference: &[f32], profile: &[f32]) -> (f64, f64) {
+    let scales = (-30..=30).map(|k| 1.0 + k as f64 * 0.005);
+    scales
+        .flat_map(|s| (-75..=75).map(move |k| (s, k as f64 * 0.002)))
+        .map(|(s, t)| (s, t, correlation(reference, profile, s, t)))
+        .max_by(|(_, _, c1), (_, _, c2)| c1.total_cmp(c2))
+        .map_or((1.0, 0.0), |(s, t, _)| (s, t))
+}
@@ -0,0 +1,335 @@
+//! Documents of several pages, from multi-page TIFFs, PDFs or lists of images,
+//! annotated one page at a time.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use egui_inspect::egui::{self, ColorImage, DragValue, ScrollArea};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    ColorType,
+};
+
+use crate::{
+    error::{AtPath, Result},
+    img_to_cim, open_image,
+    pdf::{self, PdfDocument},
+    NamedGrid,
+};
+
+/// Inclusive range of pages, numbered from 1, open ended when `last` is `None`.
+#[derive(Clone, Copy)]
+pub struct PageRange {
+    pub first: usize,
+    pub last: Option<usize>,
+}
+
+impl Default for PageRange {
+    fn default() -> Self {
+        Self {
+            first: 1,
+            last: None,
+        }
+    }
+}
+
+impl FromStr for PageRange {
+    type Err = String;
+
+    /// Parses `3`, `2-5`, `2-` or `-5`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let page = |s: &str| match s.trim().parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid page number {s:?}")),
+            Ok(n) => Ok(n),
+        };
+        let range = match s.split_once('-') {
+            None => Self {
+                first: page(s)?,
+                last: Some(page(s)?),
+            },
+            Some((first, last)) => Self {
+                first: match first.trim().is_empty() {
+                    true => 1,
+                    false => page(first)?,
+                },
+                last: match last.trim().is_empty() {
+                    true => None,
+                    false => Some(page(last)?),
+                },
+            },
+        };
+        match range.last.is_some_and(|last| last < range.first) {
+            true => Err(format!("empty page range {s:?}")),
+            false => Ok(range),
+        }
+    }
+}
+
+impl PageRange {
+    /// Pages of the range present in a document of `count` pages.
+    pub fn pages(&self, count: usize) -> impl Iterator<Item = usize> {
+        self.first..=self.last.unwrap_or(count).min(count)
+    }
+}
+
+fn is_tiff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+fn tiff_decoder(path: &Path) -> Result<Decoder<BufReader<File>>> {
+    Decoder::new(BufReader::new(File::open(path).at(path)?)).at(path)
+}
+
+fn tiff_frame_count(path: &Path) -> Result<usize> {
+    let mut decoder = tiff_decoder(path)?;
+    let mut count = 1;
+    while decoder.more_images() {
+        decoder.next_image().at(path)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Decodes frame `frame` (from 0) of a multi-page TIFF.
+fn tiff_frame(path: &Path, frame: usize) -> Result<ColorImage> {
+    let mut decoder = tiff_decoder(path)?;
+    decoder.seek_to_image(frame).at(path)?;
+    let (w, h) = decoder.dimensions().at(path)?;
+    let colortype = decoder.colortype().at(path)?;
+    let img = match (colortype, decoder.read_image().at(path)?) {
+        (ColorType::Gray(1), DecodingResult::U8(data)) => {
+            // packed rows, already inverted by the decoder for white-is-zero images
+            let stride = (w as usize).div_ceil(8);
+            (data.len() >= stride * h as usize)
+                .then(|| {
+                    GrayImage::from_fn(w, h, |i, j| {
+                        let byte = data[j as usize * stride + i as usize / 8];
+                        Luma([255 * ((byte >> (7 - i % 8)) & 1)])
+                    })
+                })
+                .map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            GrayImage::from_raw(w, h, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgba8)
+        }
+        (colortype, _) => {
+            let message = format!(
+                "{}: unsupported TIFF color type {colortype:?}",
+                path.display()
+            );
+            return Err(message.into());
+        }
+    };
+    let img = img.ok_or_else(|| format!("{}: truncated TIFF image", path.display()))?;
+    Ok(img_to_cim(img))
+}
+
+enum PageSource {
+    Image(PathBuf),
+    /// Frame (from 0) of a multi-page TIFF.
+    TiffFrame(PathBuf, usize),
+    /// Page (from 1) of one of the document's PDFs.
+    Pdf(usize, usize),
+}
+
+/// Annotation of a page while another one is shown. The image itself is dropped
+/// and rendered again when returning to the page.
+pub struct PageState {
+    pub theta: f32,
+    pub corners: [[f64; 2]; 4],
+    pub perspective: bool,
+    pub grids: Vec<NamedGrid>,
+    pub selected: usize,
+}
+
+pub struct Page {
+    pub label: String,
+    source: PageSource,
+    pub state: Option<PageState>,
+}
+
+impl Page {
+    /// Marker of the page's extraction progress, for the page list.
+    fn status(&self) -> &'static str {
+        let Some(state) = &self.state else {
+            return "";
+        };
+        if state.grids.iter().any(|g| g.is_extracting()) {
+            " …"
+        } else if state.grids.iter().any(|g| g.table().is_some()) {
+            " ✔"
+        } else {
+            " •"
+        }
+    }
+}
+
+pub struct Document {
+    pdfs: Vec<PdfDocument>,
+    pub pages: Vec<Page>,
+    /// Index of the page being annotated.
+    pub current: usize,
+    /// Resolution PDF pages are rasterized at.
+    pub dpi: f32,
+    /// Whether newly visited pages start from the grids of the page they were
+    /// reached from (the one last worked on, not necessarily the one before).
+    pub reuse_grid: bool,
+}
+
+impl Document {
+    /// The pages of each file in turn, every page of PDFs and multi-page TIFFs.
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        let mut document = Self {
+            pdfs: vec![],
+            pages: vec![],
+            current: 0,
+            dpi: pdf::DEFAULT_DPI,
+            reuse_grid: true,
+        };
+        for path in paths {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let label = |page: usize, count: usize| match count {
+                1 => name.to_string(),
+                _ => format!("{name} p{page}"),
+            };
+            let tiff_frames = match is_tiff(path) {
+                true => tiff_frame_count(path)?,
+                false => 1,
+            };
+            if pdf::is_pdf(path) {
+                let doc = PdfDocument::open(path).at(path)?;
+                let count = doc.page_count();
+                document.pages.extend((1..=count).map(|page| Page {
+                    label: label(page, count),
+                    source: PageSource::Pdf(document.pdfs.len(), page),
+                    state: None,
+                }));
+                document.pdfs.push(doc);
+            } else if tiff_frames > 1 {
+                document.pages.extend((0..tiff_frames).map(|frame| Page {
+                    label: label(frame + 1, tiff_frames),
+                    source: PageSource::TiffFrame(path.clone(), frame),
+                    state: None,
+                }));
+            } else {
+                document.pages.push(Page {
+                    label: name.to_string(),
+                    source: PageSource::Image(path.clone()),
+                    state: None,
+                });
+            }
+        }
+        match document.pages.is_empty() {
+            true => Err("no pages to open".into()),
+            false => Ok(document),
+        }
+    }
+
+    pub fn render(&self, k: usize) -> Result<ColorImage> {
+        match &self.pages[k].source {
+            PageSource::Image(path) => open_image(path).at(path),
+            PageSource::TiffFrame(path, frame) => tiff_frame(path, *frame),
+            PageSource::Pdf(doc, page) => {
+                let pdf = &self.pdfs[*doc];
+                pdf.render_page(*page, self.dpi).at(&pdf.path)
+            }
+        }
+    }
+
+    /// Resolution the current page is rendered at, for PDF pages.
+    pub fn current_dpi(&self) -> Option<f32> {
+        match self.pages[self.current].source {
+            PageSource::Pdf(..) => Some(self.dpi),
+            _ => None,
+        }
+    }
+
+    /// Whether the page list is worth showing, for several pages or to change the
+    /// resolution of PDFs.
+    pub fn is_multipage(&self) -> bool {
+        self.pages.len() > 1 || !self.pdfs.is_empty()
+    }
+
+    /// Page list sidebar, returns the page to show when one is picked (or the
+    /// current one to reload).
+    pub fn inspect(&mut self, ui: &mut egui::Ui) -> Option<usize> {
+        let mut picked = None;
+        ui.heading("Pages");
+        ui.checkbox(&mut self.reuse_grid, "Reuse last page's grids")
+            .on_hover_text("New pages start from the grids of the page last shown");
+        if !self.pdfs.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label("PDF DPI");
+                ui.add(DragValue::new(&mut self.dpi).range(50.0..=600.0).speed(5.0));
+                if ui.button("Reload page").clicked() {
+                    picked = Some(self.current);
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Previous").clicked() && self.current > 0 {
+                picked = Some(self.current - 1);
+            }
+            if ui.button("Next").clicked() && self.current + 1 < self.pages.len() {
+                picked = Some(self.current + 1);
+            }
+        });
+        ui.separator();
+        ScrollArea::vertical().show(ui, |ui| {
+            for (k, page) in self.pages.iter().enumerate() {
+                let label = format!("{}{}", page.label, page.status());
+                if ui.selectable_label(k == self.current, label).clicked() && k != self.current {
+                    picked = Some(k);
+                }
+            }
+        });
+        picked
+    }
+}
+
+/// An image, or a page of a document with its number (from 1).
+pub type NumberedPage = (Option<usize>, ColorImage);
+
+/// Pages of an image, PDF or multi-page TIFF within `range`, a single unnumbered
+/// one for images.
+pub fn open_pages(path: &Path, range: PageRange, dpi: f32) -> Result<Vec<NumberedPage>> {
+    let document = Document {
+        dpi,
+        ..Document::open(&[path.to_path_buf()])?
+    };
+    if let [Page {
+        source: PageSource::Image(_),
+        ..
+    }] = &document.pages[..]
+    {
+        return Ok(vec![(None, document.render(0)?)]);
+    }
+    let count = document.pages.len();
+    let pages: Vec<_> = range.pages(count).collect();
+    if pages.is_empty() {
+        return Err(format!("no pages in range, the document has {count}").into());
+    }
+    pages
+        .into_iter()
+        .map(|page| Ok((Some(page), document.render(page - 1)?)))
+        .collect()
+}
@@ -0,0 +1,121 @@
+//! Errors of loading, OCR and export, reported in the error panel (or on stderr
+//! from the command line) rather than aborting.
+
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+use image::ImageError;
+use rust_xlsxwriter::XlsxError;
+use tiff::TiffError;
+
+use crate::pdf::PdfError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file.
+    Io(PathBuf, io::Error),
+    Image(PathBuf, ImageError),
+    Pdf(PathBuf, PdfError),
+    Tiff(PathBuf, TiffError),
+    Template(PathBuf, serde_json::Error),
+    Xlsx(PathBuf, XlsxError),
+    /// The OCR command could not be run, e.g. its program is not installed.
+    Backend(String, io::Error),
+    /// The OCR program ran past the cell timeout and was killed.
+    Timeout(String, Duration),
+    /// The extraction was cancelled before the cell was done.
+    Cancelled,
+    /// Cells whose OCR failed with the same error, keeping any earlier text.
+    Cells {
+        cells: Vec<(usize, usize)>,
+        source: Box<Error>,
+    },
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Image(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Pdf(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Tiff(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Template(path, e) => write!(f, "{}: invalid template, {e}", path.display()),
+            Error::Xlsx(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Backend(program, e) if e.kind() == io::ErrorKind::NotFound => {
+                write!(f, "OCR program {program:?} not found, is it installed?")
+            }
+            Error::Backend(program, e) => write!(f, "could not run OCR program {program:?}, {e}"),
+            Error::Timeout(program, timeout) => write!(
+                f,
+                "OCR program {program:?} timed out after {}s",
+                timeout.as_secs_f32()
+            ),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::Cells { cells, source } => match cells[..] {
+                [(row, col)] => write!(f, "cell {row},{col}: {source}"),
+                _ => write!(f, "{source} ({} cells)", cells.len()),
+            },
+            Error::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Invalid(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Invalid(message.to_string())
+    }
+}
+
+/// Attaches the path of the file being read or written to an error.
+pub trait AtPath<T> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T>;
+}
+
+impl<T> AtPath<T> for std::result::Result<T, io::Error> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| Error::Io(path.into(), e))
+    }
+}
+
+impl<T> AtPath<T> for std::result::Result<T, ImageError> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| match e {
+            ImageError::IoError(e) => Error::Io(path.into(), e),
+            e => Error::Image(path.into(), e),
+        })
+    }
+}
+
+impl<T> AtPath<T> for std::result::Result<T, PdfError> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| Error::Pdf(path.into(), e))
+    }
+}
+
+impl<T> AtPath<T> for std::result::Result<T, TiffError> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| Error::Tiff(path.into(), e))
+    }
+}
+
+impl<T> AtPath<T> for std::result::Result<T, serde_json::Error> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| Error::Template(path.into(), e))
+    }
+}
+
+impl<T> AtPath<T> for std::result::Result<T, XlsxError> {
+    fn at(self, path: impl Into<PathBuf>) -> Result<T> {
+        self.map_err(|e| Error::Xlsx(path.into(), e))
+    }
+}
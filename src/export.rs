@@ -0,0 +1,139 @@
+//! Exports of several extracted tables to a single file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+
+use crate::{error::AtPath, TableEdit};
+
+/// Characters not allowed in worksheet names.
+const SHEET_NAME_FORBIDDEN: &str = "[]:*?/\\";
+
+/// A valid worksheet name close to `name`, distinct from those already `taken`.
+fn sheet_name(name: &str, taken: &[String]) -> String {
+    let base: String = name
+        .chars()
+        .map(|c| match SHEET_NAME_FORBIDDEN.contains(c) {
+            true => '_',
+            false => c,
+        })
+        .take(28)
+        .collect();
+    let base = match base.trim_matches(['\'', ' ']).is_empty() {
+        true => "Table".to_string(),
+        false => base.trim_matches('\'').to_string(),
+    };
+    let mut name = base.clone();
+    let mut k = 2;
+    while taken.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+        name = format!("{base} {k}");
+        k += 1;
+    }
+    name
+}
+
+/// Writes each table to its own sheet of an xlsx workbook, with merged regions as
+/// merged ranges.
+pub fn xlsx_workbook(
+    tables: &[(&str, &TableEdit)],
+    path: impl AsRef<Path>,
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let mut names = vec![];
+    let format = Format::new();
+    for (name, table) in tables {
+        let sheet = workbook.add_worksheet();
+        let name = sheet_name(name, &names);
+        sheet.set_name(name.as_str())?;
+        names.push(name);
+
+        for (i, row) in table.items.iter().enumerate() {
+            for (j, item) in row.iter().enumerate() {
+                if table.span_at(i, j).is_none() {
+                    sheet.write_string(i as u32, j as u16, item)?;
+                }
+            }
+        }
+        for span in table.spans.iter() {
+            sheet.merge_range(
+                span.row as u32,
+                span.col as u16,
+                (span.row + span.rowspan - 1) as u32,
+                (span.col + span.colspan - 1) as u16,
+                &table.items[span.row][span.col],
+                &format,
+            )?;
+        }
+    }
+    workbook.save(path)
+}
+
+#[derive(Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Html,
+    Xlsx,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Html => "html",
+            OutputFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// Format given by a file extension, csv if unknown.
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_lowercase().as_str() {
+            "xlsx" => OutputFormat::Xlsx,
+            "html" | "htm" => OutputFormat::Html,
+            _ => OutputFormat::Csv,
+        }
+    }
+}
+
+/// `path` with `_{suffix}` appended to its file stem.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{stem}_{suffix}.{}", ext.to_string_lossy())),
+        None => path.with_file_name(format!("{stem}_{suffix}")),
+    }
+}
+
+/// Writes named tables to `path` in the format given by its extension, returning
+/// the files written. Workbooks hold every table, while with several tables csv
+/// and html outputs are written per table, suffixed by its name.
+pub fn write_tables(
+    tables: &[(String, TableEdit)],
+    path: &Path,
+) -> crate::error::Result<Vec<PathBuf>> {
+    let format = OutputFormat::from_path(path);
+    if format == OutputFormat::Xlsx {
+        let tables: Vec<_> = tables.iter().map(|(n, t)| (n.as_str(), t)).collect();
+        xlsx_workbook(&tables, path).at(path)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut written = vec![];
+    for (name, table) in tables.iter() {
+        let path = match tables.len() {
+            1 => path.to_path_buf(),
+            _ => with_suffix(path, name),
+        };
+        let contents = match format {
+            OutputFormat::Html => table.html(),
+            _ => table.csv(),
+        };
+        fs::write(&path, contents).at(&path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
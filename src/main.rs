@@ -1,27 +1,60 @@
+mod backend;
+mod batch;
+mod cli;
+mod confidence;
+mod continued;
+mod detect;
+mod document;
+mod error;
+mod export;
+mod pdf;
+mod review;
+mod template;
+mod workspace;
+
 use std::{
     cell::RefCell,
+    collections::BTreeSet,
     f32::consts::PI,
-    fs, io,
+    fmt::Display,
+    fs,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
+use backend::{
+    BackendConfig, Cuneiform, Mock, Recognition, RunContext, Tesseract, DEFAULT_CELL_TIMEOUT,
+    DEFAULT_ENGINE_THREADS,
+};
+use batch::{BatchDialog, Extraction};
+use clap::{Parser, ValueEnum};
+use detect::{InkMask, LineDetection};
+use document::{Document, PageState};
 use egui_extras::{Column, TableBuilder};
 use egui_inspect::{background_task::BackgroundTask, EguiInspect};
 use egui_inspect::{
     background_task::Task,
     egui::{
-        self, vec2, CentralPanel, Color32, ColorImage, Context, ScrollArea, Slider, Stroke,
-        TextureHandle, TextureOptions, Vec2, Window,
+        self, vec2, CentralPanel, Color32, ColorImage, ComboBox, Context, DragValue, Id,
+        ScrollArea, SidePanel, Slider, Stroke, TextEdit, TextureHandle, TextureOptions,
+        TopBottomPanel, Vec2, Window,
     },
 };
-use egui_plot::{Plot, PlotImage, PlotPoint, PlotUi, Polygon};
-use image::{ColorType, ImageResult, RgbaImage};
-use imageproc::geometric_transformations::{self, rotate_about_center};
+use egui_plot::{Line, MarkerShape, Plot, PlotImage, PlotPoint, PlotUi, Points, Polygon};
+use error::{AtPath, Error};
+use image::{ImageResult, RgbaImage};
+use imageproc::geometric_transformations::{self, rotate_about_center, warp, Projection};
 use iter_tools::Itertools;
 
-use rayon::iter::ParallelBridge;
-use rayon::prelude::*;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use review::Review;
+use serde::{Deserialize, Serialize};
+use template::{Reference, Template, TemplateGrid, PROFILE_BINS};
+use workspace::Workspace;
 
 thread_local! {
     static SHARED_STATE: RefCell<SharedState> = Default::default();
@@ -33,6 +66,8 @@ struct SharedState {
     drag_enabled: bool,
     delta_x: f64,
     delta_y: f64,
+    /// Errors shown in the error panel, with how many times each occurred.
+    errors: Vec<(String, usize)>,
 }
 
 impl Default for SharedState {
@@ -43,90 +78,317 @@ impl Default for SharedState {
             drag_enabled: Default::default(),
             delta_x: 0.005,
             delta_y: 0.005,
+            errors: vec![],
         }
     }
 }
 
-#[derive(Clone)]
+/// Shows an error in the error panel, counting repeats of the same message.
+fn report_error(e: impl Display) {
+    let message = e.to_string();
+    SHARED_STATE.with_borrow_mut(
+        |ss| match ss.errors.iter_mut().find(|(m, _)| *m == message) {
+            Some((_, count)) => *count += 1,
+            None => ss.errors.push((message, 1)),
+        },
+    );
+}
+
+/// A control point bending a separator, offsetting it (across its length) at a
+/// position along it.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Bend {
+    at: f64,
+    offset: f64,
+}
+
+/// Offset of a separator at `t` along it, linear between its bends (sorted by
+/// position) and constant past the outermost ones.
+fn bend_offset(bends: &[Bend], t: f64) -> f64 {
+    match bends.iter().position(|b| t < b.at) {
+        None => bends.last().map_or(0.0, |b| b.offset),
+        Some(0) => bends[0].offset,
+        Some(k) => {
+            let (b0, b1) = (bends[k - 1], bends[k]);
+            b0.offset + (b1.offset - b0.offset) * (t - b0.at) / (b1.at - b0.at)
+        }
+    }
+}
+
+/// Smallest and largest offset of a separator anywhere along it.
+fn bend_range(bends: &[Bend]) -> (f64, f64) {
+    bends
+        .iter()
+        .map(|b| b.offset)
+        .minmax()
+        .into_option()
+        .unwrap_or((0.0, 0.0))
+}
+
+/// Positions from `from` to `to` at which the separator changes direction.
+fn bend_breaks(bends: &[Bend], from: f64, to: f64) -> Vec<f64> {
+    [from]
+        .into_iter()
+        .chain(bends.iter().map(|b| b.at).filter(|&t| from < t && t < to))
+        .chain([to])
+        .collect()
+}
+
+fn sort_bends(bends: &mut [Bend]) {
+    bends.sort_by(|b1, b2| b1.at.total_cmp(&b2.at));
+}
+
+fn plot_bends(pui: &mut PlotUi, handles: Vec<[f64; 2]>, color: Color32) {
+    if !handles.is_empty() {
+        pui.points(
+            Points::new(handles)
+                .shape(MarkerShape::Square)
+                .radius(4.0)
+                .color(color),
+        );
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct VertSep {
     x: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bends: Vec<Bend>,
 }
 
 impl VertSep {
+    fn new(x: f64) -> Self {
+        Self { x, bends: vec![] }
+    }
+    fn x_at(&self, y: f64) -> f64 {
+        self.x + bend_offset(&self.bends, y)
+    }
+    fn x_range(&self) -> (f64, f64) {
+        let (lo, hi) = bend_range(&self.bends);
+        (self.x + lo, self.x + hi)
+    }
     fn translate(&mut self, s: Vec2) {
         self.x += s.x as f64;
+        for b in self.bends.iter_mut() {
+            b.at += s.y as f64;
+        }
     }
     fn in_bounds(&mut self, delta_x: f64, extents: &Extents, pointer: PlotPoint) -> bool {
-        self.x - delta_x < pointer.x
-            && pointer.x < self.x + delta_x
+        let x = self.x_at(pointer.y);
+        x - delta_x < pointer.x
+            && pointer.x < x + delta_x
             && extents.ymin < pointer.y
             && pointer.y < extents.ymax
     }
+    fn bend_near(&self, delta_x: f64, delta_y: f64, pointer: PlotPoint) -> Option<usize> {
+        self.bends.iter().position(|b| {
+            (self.x + b.offset - pointer.x).abs() < 2.0 * delta_x
+                && (b.at - pointer.y).abs() < 2.0 * delta_y
+        })
+    }
+    /// Adds a bend at the pointer, or removes the one under it.
+    fn toggle_bend(&mut self, ss: &SharedState, pointer: PlotPoint) -> bool {
+        if let Some(k) = self.bend_near(ss.delta_x, ss.delta_y, pointer) {
+            self.bends.remove(k);
+        } else if self.in_bounds(ss.delta_x, &ss.extents, pointer) {
+            let offset = bend_offset(&self.bends, pointer.y);
+            self.bends.push(Bend {
+                at: pointer.y,
+                offset,
+            });
+            sort_bends(&mut self.bends);
+        } else {
+            return false;
+        }
+        true
+    }
     fn plot_inspect(&mut self, pui: &mut PlotUi) {
         SHARED_STATE.with_borrow_mut(|ss| {
-            pui.polygon(
-                Polygon::new(vec![
-                    [self.x - ss.delta_x, ss.extents.ymin - ss.delta_y],
-                    [self.x + ss.delta_x, ss.extents.ymin - ss.delta_y],
-                    [self.x + ss.delta_x, ss.extents.ymax + ss.delta_y],
-                    [self.x - ss.delta_x, ss.extents.ymax + ss.delta_y],
-                ])
-                .fill_color(ss.separator_color)
-                .stroke(Stroke::NONE),
+            let ys = bend_breaks(
+                &self.bends,
+                ss.extents.ymin - ss.delta_y,
+                ss.extents.ymax + ss.delta_y,
             );
+            for yw in ys.windows(2) {
+                let (x0, x1) = (self.x_at(yw[0]), self.x_at(yw[1]));
+                pui.polygon(
+                    Polygon::new(vec![
+                        [x0 - ss.delta_x, yw[0]],
+                        [x0 + ss.delta_x, yw[0]],
+                        [x1 + ss.delta_x, yw[1]],
+                        [x1 - ss.delta_x, yw[1]],
+                    ])
+                    .fill_color(ss.separator_color)
+                    .stroke(Stroke::NONE),
+                );
+            }
+            let handles = self.bends.iter().map(|b| [self.x + b.offset, b.at]);
+            plot_bends(pui, handles.collect(), ss.separator_color);
 
             if let Some(pointer) = pui.pointer_coordinate() {
-                if self.in_bounds(ss.delta_x, &ss.extents, pointer) && ss.drag_enabled {
-                    ss.drag_enabled = false;
-                    self.translate(pui.pointer_coordinate_drag_delta());
+                if ss.drag_enabled {
+                    let dd = pui.pointer_coordinate_drag_delta();
+                    if let Some(k) = self.bend_near(ss.delta_x, ss.delta_y, pointer) {
+                        ss.drag_enabled = false;
+                        self.bends[k].at += dd.y as f64;
+                        self.bends[k].offset += dd.x as f64;
+                        sort_bends(&mut self.bends);
+                    } else if self.in_bounds(ss.delta_x, &ss.extents, pointer) {
+                        ss.drag_enabled = false;
+                        self.x += dd.x as f64;
+                    }
                 }
             }
         });
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct HorizSep {
     y: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bends: Vec<Bend>,
 }
 
 impl HorizSep {
+    fn new(y: f64) -> Self {
+        Self { y, bends: vec![] }
+    }
+    fn y_at(&self, x: f64) -> f64 {
+        self.y + bend_offset(&self.bends, x)
+    }
+    fn y_range(&self) -> (f64, f64) {
+        let (lo, hi) = bend_range(&self.bends);
+        (self.y + lo, self.y + hi)
+    }
     fn translate(&mut self, s: Vec2) {
         self.y += s.y as f64;
+        for b in self.bends.iter_mut() {
+            b.at += s.x as f64;
+        }
     }
     fn in_bounds(&mut self, delta_y: f64, extents: &Extents, pointer: PlotPoint) -> bool {
-        self.y - delta_y < pointer.y
-            && pointer.y < self.y + delta_y
+        let y = self.y_at(pointer.x);
+        y - delta_y < pointer.y
+            && pointer.y < y + delta_y
             && extents.xmin < pointer.x
             && pointer.x < extents.xmax
     }
+    fn bend_near(&self, delta_x: f64, delta_y: f64, pointer: PlotPoint) -> Option<usize> {
+        self.bends.iter().position(|b| {
+            (b.at - pointer.x).abs() < 2.0 * delta_x
+                && (self.y + b.offset - pointer.y).abs() < 2.0 * delta_y
+        })
+    }
+    /// Adds a bend at the pointer, or removes the one under it.
+    fn toggle_bend(&mut self, ss: &SharedState, pointer: PlotPoint) -> bool {
+        if let Some(k) = self.bend_near(ss.delta_x, ss.delta_y, pointer) {
+            self.bends.remove(k);
+        } else if self.in_bounds(ss.delta_y, &ss.extents, pointer) {
+            let offset = bend_offset(&self.bends, pointer.x);
+            self.bends.push(Bend {
+                at: pointer.x,
+                offset,
+            });
+            sort_bends(&mut self.bends);
+        } else {
+            return false;
+        }
+        true
+    }
     fn plot_inspect(&mut self, pui: &mut PlotUi) {
         SHARED_STATE.with_borrow_mut(|ss| {
-            pui.polygon(
-                Polygon::new(vec![
-                    [ss.extents.xmin - ss.delta_x, self.y - ss.delta_y],
-                    [ss.extents.xmin - ss.delta_x, self.y + ss.delta_y],
-                    [ss.extents.xmax + ss.delta_x, self.y + ss.delta_y],
-                    [ss.extents.xmax + ss.delta_x, self.y - ss.delta_y],
-                ])
-                .fill_color(ss.separator_color)
-                .stroke(Stroke::NONE),
+            let xs = bend_breaks(
+                &self.bends,
+                ss.extents.xmin - ss.delta_x,
+                ss.extents.xmax + ss.delta_x,
             );
+            for xw in xs.windows(2) {
+                let (y0, y1) = (self.y_at(xw[0]), self.y_at(xw[1]));
+                pui.polygon(
+                    Polygon::new(vec![
+                        [xw[0], y0 - ss.delta_y],
+                        [xw[0], y0 + ss.delta_y],
+                        [xw[1], y1 + ss.delta_y],
+                        [xw[1], y1 - ss.delta_y],
+                    ])
+                    .fill_color(ss.separator_color)
+                    .stroke(Stroke::NONE),
+                );
+            }
+            let handles = self.bends.iter().map(|b| [b.at, self.y + b.offset]);
+            plot_bends(pui, handles.collect(), ss.separator_color);
 
             if let Some(pointer) = pui.pointer_coordinate() {
-                if self.in_bounds(ss.delta_y, &ss.extents, pointer) && ss.drag_enabled {
-                    ss.drag_enabled = false;
-                    self.translate(pui.pointer_coordinate_drag_delta());
+                if ss.drag_enabled {
+                    let dd = pui.pointer_coordinate_drag_delta();
+                    if let Some(k) = self.bend_near(ss.delta_x, ss.delta_y, pointer) {
+                        ss.drag_enabled = false;
+                        self.bends[k].at += dd.x as f64;
+                        self.bends[k].offset += dd.y as f64;
+                        sort_bends(&mut self.bends);
+                    } else if self.in_bounds(ss.delta_y, &ss.extents, pointer) {
+                        ss.drag_enabled = false;
+                        self.y += dd.y as f64;
+                    }
                 }
             }
         });
     }
 }
 
-#[derive(Clone)]
+/// A rectangular block of cells, indexed from the top left of the table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct CellSpan {
+    row: usize,
+    col: usize,
+    rowspan: usize,
+    colspan: usize,
+}
+
+impl CellSpan {
+    fn single(row: usize, col: usize) -> Self {
+        Self {
+            row,
+            col,
+            rowspan: 1,
+            colspan: 1,
+        }
+    }
+    fn contains(&self, i: usize, j: usize) -> bool {
+        self.row <= i && i < self.row + self.rowspan && self.col <= j && j < self.col + self.colspan
+    }
+    fn overlaps(&self, other: &CellSpan) -> bool {
+        self.row < other.row + other.rowspan
+            && other.row < self.row + self.rowspan
+            && self.col < other.col + other.colspan
+            && other.col < self.col + self.colspan
+    }
+}
+
+/// A merged cell region, kept as points in two opposite corner cells (rather than
+/// cell indices) so that it follows the separators as they are moved or added.
+#[derive(Clone, Serialize, Deserialize)]
+struct Merge {
+    from: [f64; 2],
+    to: [f64; 2],
+}
+
+impl Merge {
+    fn translate(&mut self, s: Vec2) {
+        for p in [&mut self.from, &mut self.to] {
+            p[0] += s.x as f64;
+            p[1] += s.y as f64;
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Grid {
     horizontals: Vec<HorizSep>,
     verticals: Vec<VertSep>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    merges: Vec<Merge>,
 }
 
 impl Default for Grid {
@@ -134,28 +396,245 @@ impl Default for Grid {
         let mut horizontals = vec![];
         let mut verticals = vec![];
         for y in [0.8, 0.9] {
-            horizontals.push(HorizSep { y });
+            horizontals.push(HorizSep::new(y));
         }
         for x in [0.1, 0.2] {
-            verticals.push(VertSep { x });
+            verticals.push(VertSep::new(x));
         }
         Self {
             horizontals,
             verticals,
+            merges: vec![],
         }
     }
 }
 
 impl Grid {
     fn sort_horiz(&mut self) {
-        self.horizontals
-            .sort_by(|h1, h2| h1.y.partial_cmp(&h2.y).unwrap());
+        self.horizontals.sort_by(|h1, h2| h1.y.total_cmp(&h2.y));
     }
     fn sort_vert(&mut self) {
-        self.verticals
-            .sort_by(|v1, v2| v1.x.partial_cmp(&v2.x).unwrap());
+        self.verticals.sort_by(|v1, v2| v1.x.total_cmp(&v2.x));
+    }
+    /// Checks a grid read from a file, which the annotation expects to have at least
+    /// two separators each way, in increasing order within the image.
+    fn validate(&self) -> Result<(), String> {
+        let in_image = |v: f64| (0.0..=1.0).contains(&v);
+        let check = |axis: &str, positions: Vec<f64>| {
+            if positions.len() < 2 {
+                return Err(format!("has fewer than 2 {axis} separators"));
+            }
+            if !positions.iter().all(|&v| in_image(v)) {
+                return Err(format!("has {axis} separators outside [0, 1]"));
+            }
+            if !positions.windows(2).all(|w| w[0] < w[1]) {
+                return Err(format!("has {axis} separators out of order"));
+            }
+            Ok(())
+        };
+        check("horizontal", self.horizontals.iter().map(|h| h.y).collect())?;
+        check("vertical", self.verticals.iter().map(|v| v.x).collect())?;
+        let merge_in_image = |m: &Merge| m.from.into_iter().chain(m.to).all(in_image);
+        match self.merges.iter().all(merge_in_image) {
+            true => Ok(()),
+            false => Err("has merges outside [0, 1]".to_string()),
+        }
+    }
+    fn extents(&self) -> Extents {
+        Extents {
+            xmin: self.verticals.first().unwrap().x,
+            xmax: self.verticals.last().unwrap().x,
+            ymin: self.horizontals.first().unwrap().y,
+            ymax: self.horizontals.last().unwrap().y,
+        }
+    }
+    /// Replaces interior separators with detected positions, keeping the outer
+    /// (first and last) ones in place.
+    fn set_interior(&mut self, ys: Vec<f64>, xs: Vec<f64>) {
+        let Extents {
+            xmin,
+            xmax,
+            ymin,
+            ymax,
+        } = self.extents();
+        self.horizontals = [ymin]
+            .into_iter()
+            .chain(ys)
+            .chain([ymax])
+            .map(HorizSep::new)
+            .collect();
+        self.verticals = [xmin]
+            .into_iter()
+            .chain(xs)
+            .chain([xmax])
+            .map(VertSep::new)
+            .collect();
+    }
+    /// Replaces separators with detected positions, for each direction in which at
+    /// least two (the table's outer bounds) were found.
+    fn set_detected(&mut self, ys: Vec<f64>, xs: Vec<f64>) {
+        if ys.len() >= 2 {
+            self.horizontals = ys.into_iter().map(HorizSep::new).collect();
+        }
+        if xs.len() >= 2 {
+            self.verticals = xs.into_iter().map(VertSep::new).collect();
+        }
+    }
+    fn translate(&mut self, s: Vec2) {
+        for v in self.verticals.iter_mut() {
+            v.translate(s)
+        }
+        for h in self.horizontals.iter_mut() {
+            h.translate(s)
+        }
+        for m in self.merges.iter_mut() {
+            m.translate(s)
+        }
+    }
+    fn nrows(&self) -> usize {
+        self.horizontals.len().saturating_sub(1)
+    }
+    fn ncols(&self) -> usize {
+        self.verticals.len().saturating_sub(1)
+    }
+    /// Row and column of the cell containing a point, expects a sorted grid.
+    fn cell_at(&self, [x, y]: [f64; 2]) -> Option<(usize, usize)> {
+        let j = self
+            .verticals
+            .windows(2)
+            .position(|vw| vw[0].x_at(y) <= x && x < vw[1].x_at(y))?;
+        let k = self
+            .horizontals
+            .windows(2)
+            .position(|hw| hw[0].y_at(x) <= y && y < hw[1].y_at(x))?;
+        Some((self.nrows() - 1 - k, j))
+    }
+    fn merge_span(&self, merge: &Merge) -> Option<CellSpan> {
+        let (i1, j1) = self.cell_at(merge.from)?;
+        let (i2, j2) = self.cell_at(merge.to)?;
+        Some(CellSpan {
+            row: i1.min(i2),
+            col: j1.min(j2),
+            rowspan: i1.abs_diff(i2) + 1,
+            colspan: j1.abs_diff(j2) + 1,
+        })
+    }
+    /// Merged regions in cell indices, skipping those within a single cell or
+    /// overlapping an earlier merge.
+    fn spans(&self) -> Vec<CellSpan> {
+        let mut spans: Vec<CellSpan> = vec![];
+        for span in self.merges.iter().filter_map(|m| self.merge_span(m)) {
+            if span != CellSpan::single(span.row, span.col)
+                && !spans.iter().any(|other| other.overlaps(&span))
+            {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+    /// Every region to extract, merged ones once along with all cells they do not cover.
+    fn cells(&self) -> Vec<CellSpan> {
+        let spans = self.spans();
+        let mut cells = spans.clone();
+        for i in 0..self.nrows() {
+            for j in 0..self.ncols() {
+                if !spans.iter().any(|span| span.contains(i, j)) {
+                    cells.push(CellSpan::single(i, j));
+                }
+            }
+        }
+        cells
+    }
+    /// Normalized bounds `[x1, x2, y1, y2]` of a region, expects a sorted grid.
+    fn cell_bounds(&self, cell: &CellSpan) -> [f64; 4] {
+        let top = self.horizontals.len() - 1 - cell.row;
+        [
+            self.verticals[cell.col].x,
+            self.verticals[cell.col + cell.colspan].x,
+            self.horizontals[top - cell.rowspan].y,
+            self.horizontals[top].y,
+        ]
+    }
+    /// Removes any merges covering the cell at a point, returns whether there were any.
+    fn unmerge_at(&mut self, p: [f64; 2]) -> bool {
+        let Some((i, j)) = self.cell_at(p) else {
+            return false;
+        };
+        let n_merges = self.merges.len();
+        let covering: Vec<bool> = self
+            .merges
+            .iter()
+            .map(|m| self.merge_span(m).is_some_and(|span| span.contains(i, j)))
+            .collect();
+        let mut covering = covering.into_iter();
+        self.merges.retain(|_| !covering.next().unwrap());
+        self.merges.len() != n_merges
+    }
+    /// Adds or removes a bend on the separator under the pointer.
+    fn toggle_bend_at(&mut self, pointer: PlotPoint) {
+        SHARED_STATE.with_borrow(|ss| {
+            if !self
+                .horizontals
+                .iter_mut()
+                .any(|h| h.toggle_bend(ss, pointer))
+            {
+                self.verticals
+                    .iter_mut()
+                    .any(|v| v.toggle_bend(ss, pointer));
+            }
+        })
+    }
+    /// Maps every position `x` to `sx * x + tx` and `y` to `sy * y + ty`.
+    fn transform(&mut self, sx: f64, tx: f64, sy: f64, ty: f64) {
+        for v in self.verticals.iter_mut() {
+            v.x = sx * v.x + tx;
+            for b in v.bends.iter_mut() {
+                b.at = sy * b.at + ty;
+                b.offset *= sx;
+            }
+        }
+        for h in self.horizontals.iter_mut() {
+            h.y = sy * h.y + ty;
+            for b in h.bends.iter_mut() {
+                b.at = sx * b.at + tx;
+                b.offset *= sy;
+            }
+        }
+        for m in self.merges.iter_mut() {
+            for p in [&mut m.from, &mut m.to] {
+                *p = [sx * p[0] + tx, sy * p[1] + ty];
+            }
+        }
+    }
+    /// Non interactive drawing, for grids other than the one being annotated.
+    fn plot_outline(&self, pui: &mut PlotUi, color: Color32) {
+        let Extents {
+            xmin,
+            xmax,
+            ymin,
+            ymax,
+        } = self.extents();
+        for horiz in self.horizontals.iter() {
+            let xs = bend_breaks(&horiz.bends, xmin, xmax);
+            let points: Vec<_> = xs.into_iter().map(|x| [x, horiz.y_at(x)]).collect();
+            pui.line(Line::new(points).color(color));
+        }
+        for vert in self.verticals.iter() {
+            let ys = bend_breaks(&vert.bends, ymin, ymax);
+            let points: Vec<_> = ys.into_iter().map(|y| [vert.x_at(y), y]).collect();
+            pui.line(Line::new(points).color(color));
+        }
     }
     fn plot_inspect(&mut self, pui: &mut PlotUi) {
+        let merge_color = SHARED_STATE.with_borrow(|ss| ss.separator_color.gamma_multiply(0.3));
+        for span in self.spans() {
+            let [x1, x2, y1, y2] = self.cell_bounds(&span);
+            pui.polygon(
+                Polygon::new(vec![[x1, y1], [x2, y1], [x2, y2], [x1, y2]])
+                    .fill_color(merge_color)
+                    .stroke(Stroke::NONE),
+            );
+        }
         for horiz in self.horizontals.iter_mut() {
             horiz.plot_inspect(pui);
         }
@@ -173,81 +652,375 @@ struct Extents {
     ymax: f64,
 }
 
+/// Outcome of the OCR of a cell.
+#[derive(Clone, PartialEq)]
+enum CellStatus {
+    Ok,
+    /// Read nothing, or not OCRed yet.
+    Empty,
+    Failed(String),
+}
+
+#[derive(Clone)]
 struct TableEdit {
     items: Vec<Vec<String>>,
+    /// Outcome of each cell's OCR, parallel to `items`.
+    status: Vec<Vec<CellStatus>>,
+    /// Confidence (in percent) of each cell's text, for backends reporting one.
+    confidence: Vec<Vec<Option<f32>>>,
+    /// Merged regions, text is held by the top left cell of each.
+    spans: Vec<CellSpan>,
+    /// Whether cells covered by a merged region repeat its text in csv exports,
+    /// otherwise they are left blank.
+    repeat_spans: bool,
+    /// Cells picked in the table window, for OCRing them again.
+    selection: BTreeSet<(usize, usize)>,
+    /// Where the files exchanged with the OCR engine were kept, if asked to.
+    kept_files: Option<PathBuf>,
+}
+
+/// Background of a cell by the confidence of its text, from red at 0% through
+/// yellow at the low confidence threshold to green at 100%.
+fn confidence_color(confidence: f32) -> Color32 {
+    let lerp = |a: Color32, b: Color32, t: f32| {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t) as u8;
+        Color32::from_rgb(mix(a.r(), b.r()), mix(a.g(), b.g()), mix(a.b(), b.b()))
+    };
+    let (red, yellow, green) = (
+        Color32::from_rgb(110, 30, 20),
+        Color32::from_rgb(100, 85, 20),
+        Color32::from_rgb(30, 70, 35),
+    );
+    let low = confidence::LOW_CONFIDENCE;
+    match confidence < low {
+        true => lerp(red, yellow, confidence / low),
+        false => lerp(yellow, green, (confidence - low) / (100.0 - low)),
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 impl TableEdit {
-    fn csv(&self) -> String {
-        self.items
+    /// A table of `nrows` by `ncols` empty cells, not yet OCRed.
+    fn empty(nrows: usize, ncols: usize) -> Self {
+        Self {
+            items: vec![vec![String::new(); ncols]; nrows],
+            status: vec![vec![CellStatus::Empty; ncols]; nrows],
+            confidence: vec![vec![None; ncols]; nrows],
+            spans: vec![],
+            repeat_spans: false,
+            selection: BTreeSet::new(),
+            kept_files: None,
+        }
+    }
+    fn failed_cells(&self) -> Vec<(usize, usize)> {
+        (0..self.status.len())
+            .flat_map(|i| (0..self.status[i].len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| matches!(self.status[i][j], CellStatus::Failed(_)))
+            .collect()
+    }
+    /// Selects `cells`, or deselects them when all already are.
+    fn toggle_selection(&mut self, cells: Vec<(usize, usize)>) {
+        if cells.iter().all(|c| self.selection.contains(c)) {
+            for c in cells.iter() {
+                self.selection.remove(c);
+            }
+        } else {
+            self.selection.extend(cells);
+        }
+    }
+    /// The selected cells, merged regions by their top left cell.
+    fn selected_cells(&self) -> Vec<(usize, usize)> {
+        self.selection
             .iter()
-            .map(|row| row.iter().map(|item| format!("\"{item}\"")).join(", "))
+            .map(|&(i, j)| {
+                self.span_at(i, j)
+                    .map_or((i, j), |span| (span.row, span.col))
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+    fn span_at(&self, i: usize, j: usize) -> Option<&CellSpan> {
+        self.spans.iter().find(|span| span.contains(i, j))
+    }
+    fn is_covered(&self, i: usize, j: usize) -> bool {
+        self.span_at(i, j)
+            .is_some_and(|span| (span.row, span.col) != (i, j))
+    }
+    fn value(&self, i: usize, j: usize) -> &str {
+        match self.span_at(i, j) {
+            Some(span) if (span.row, span.col) != (i, j) => match self.repeat_spans {
+                true => &self.items[span.row][span.col],
+                false => "",
+            },
+            _ => &self.items[i][j],
+        }
+    }
+    fn csv(&self) -> String {
+        (0..self.items.len())
+            .map(|i| {
+                (0..self.items[i].len())
+                    .map(|j| format!("\"{}\"", self.value(i, j)))
+                    .join(", ")
+            })
+            .join("\n")
+    }
+    fn has_confidence(&self) -> bool {
+        self.confidence.iter().flatten().any(Option::is_some)
+    }
+    /// The confidence of each cell as csv, blank where none was reported and for
+    /// cells covered by a merged region.
+    fn confidence_csv(&self) -> String {
+        (0..self.items.len())
+            .map(|i| {
+                (0..self.items[i].len())
+                    .map(|j| match (self.is_covered(i, j), self.confidence[i][j]) {
+                        (false, Some(c)) => format!("{c:.1}"),
+                        _ => String::new(),
+                    })
+                    .join(", ")
+            })
             .join("\n")
     }
+    fn html(&self) -> String {
+        let rows = (0..self.items.len())
+            .map(|i| {
+                let cells = (0..self.items[i].len())
+                    .filter(|&j| !self.is_covered(i, j))
+                    .map(|j| {
+                        let mut attrs = String::new();
+                        if let Some(span) = self.span_at(i, j) {
+                            if span.rowspan > 1 {
+                                attrs += &format!(" rowspan=\"{}\"", span.rowspan);
+                            }
+                            if span.colspan > 1 {
+                                attrs += &format!(" colspan=\"{}\"", span.colspan);
+                            }
+                        }
+                        format!("<td{attrs}>{}</td>", html_escape(&self.items[i][j]))
+                    })
+                    .join("");
+                format!("  <tr>{cells}</tr>")
+            })
+            .join("\n");
+        format!("<table>\n{rows}\n</table>\n")
+    }
 }
 
 impl EguiInspect for TableEdit {
     fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {}
 
-    fn inspect_mut(&mut self, _label: &str, ui: &mut egui::Ui) {
-        Window::new("Table").min_width(500.0).show(ui.ctx(), |ui| {
-            ScrollArea::both().show(ui, |ui| {
-                let mut builder = TableBuilder::new(ui);
-                let nrows = self.items.len();
-                let ncols = self.items[0].len();
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        self.show(Id::new(("table", label)), label, ui);
+    }
+}
 
-                for _ in 0..ncols {
-                    builder = builder.column(Column::auto().resizable(true));
-                }
+impl TableEdit {
+    /// Shows the table window titled by `label`, `id` keeping its state apart from
+    /// other tables' whatever their names.
+    fn show(&mut self, id: Id, label: &str, ui: &mut egui::Ui) {
+        Window::new(format!("Table: {label}"))
+            .id(id)
+            .min_width(500.0)
+            .show(ui.ctx(), |ui| {
+                ScrollArea::both().show(ui, |ui| {
+                    let mut builder = TableBuilder::new(ui);
+                    let nrows = self.items.len();
+                    let ncols = self.items.first().map_or(0, Vec::len);
+
+                    // row selectors
+                    builder = builder.column(Column::auto());
+                    for _ in 0..ncols {
+                        builder = builder.column(Column::auto().resizable(true));
+                    }
 
-                builder.body(|body| {
-                    body.rows(30.0, nrows, |mut row| {
-                        let i = row.index();
-                        for j in 0..ncols {
-                            row.col(|ui| {
-                                self.items[i][j].inspect_mut(format!("{i},{j}").as_str(), ui);
+                    builder
+                        .header(20.0, |mut header| {
+                            header.col(|_| {});
+                            for j in 0..ncols {
+                                header.col(|ui| {
+                                    let column: Vec<_> = (0..nrows).map(|i| (i, j)).collect();
+                                    let selected =
+                                        column.iter().all(|c| self.selection.contains(c));
+                                    if ui.selectable_label(selected, format!("col {j}")).clicked() {
+                                        self.toggle_selection(column);
+                                    }
+                                });
+                            }
+                        })
+                        .body(|body| {
+                            body.rows(30.0, nrows, |mut row| {
+                                let i = row.index();
+                                row.col(|ui| {
+                                    let cells: Vec<_> = (0..ncols).map(|j| (i, j)).collect();
+                                    let selected = cells.iter().all(|c| self.selection.contains(c));
+                                    if ui.selectable_label(selected, format!("row {i}")).clicked() {
+                                        self.toggle_selection(cells);
+                                    }
+                                });
+                                for j in 0..ncols {
+                                    row.col(|ui| {
+                                        if self.is_covered(i, j) {
+                                            ui.weak(format!("{i},{j}: (merged)"));
+                                            return;
+                                        }
+                                        let selected = self.selection.contains(&(i, j));
+                                        if ui
+                                            .selectable_label(selected, format!("{i},{j}"))
+                                            .clicked()
+                                        {
+                                            self.toggle_selection(vec![(i, j)]);
+                                        }
+                                        let (bg, hover) =
+                                            match (&self.status[i][j], self.confidence[i][j]) {
+                                                (CellStatus::Failed(e), _) => {
+                                                    (Color32::from_rgb(90, 20, 20), e.clone())
+                                                }
+                                                (_, Some(c)) => (
+                                                    confidence_color(c),
+                                                    format!("confidence {c:.0}%"),
+                                                ),
+                                                _ => {
+                                                    ui.text_edit_singleline(&mut self.items[i][j]);
+                                                    return;
+                                                }
+                                            };
+                                        let before = self.items[i][j].clone();
+                                        ui.scope(|ui| {
+                                            ui.visuals_mut().extreme_bg_color = bg;
+                                            ui.text_edit_singleline(&mut self.items[i][j]);
+                                        })
+                                        .response
+                                        .on_hover_text(hover);
+                                        // typed in by hand
+                                        if self.items[i][j] != before {
+                                            self.status[i][j] = CellStatus::Ok;
+                                            self.confidence[i][j] = None;
+                                        }
+                                    });
+                                }
                             });
+                        });
+                });
+                if let Some(dir) = &self.kept_files {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Intermediate files kept in {}", dir.display()));
+                        if ui.button("Copy path").clicked() {
+                            ui.output_mut(|o| o.copied_text = dir.display().to_string());
                         }
                     });
+                }
+                if !self.selection.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} cell(s) selected", self.selection.len()));
+                        if ui.button("Clear selection").clicked() {
+                            self.selection.clear();
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Export csv").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().set_directory(".").save_file() {
+                            if let Err(e) = fs::write(&path, self.csv()).at(&path) {
+                                report_error(e);
+                            }
+                        }
+                    }
+                    ui.checkbox(&mut self.repeat_spans, "Repeat merged values");
+                    if ui.button("Export html").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().set_directory(".").save_file() {
+                            if let Err(e) = fs::write(&path, self.html()).at(&path) {
+                                report_error(e);
+                            }
+                        }
+                    }
+                    if self.has_confidence() && ui.button("Export confidence").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().set_directory(".").save_file() {
+                            if let Err(e) = fs::write(&path, self.confidence_csv()).at(&path) {
+                                report_error(e);
+                            }
+                        }
+                    }
                 });
             });
-            if ui.button("Export csv").clicked() {
-                if let Some(path) = rfd::FileDialog::new().set_directory(".").save_file() {
-                    fs::write(path, self.csv()).unwrap();
-                }
-            }
-        });
     }
 }
 
-#[allow(dead_code)]
-#[derive(EguiInspect, PartialEq)]
+/// OCR backend presets, for the command line.
+#[derive(PartialEq, Clone, Copy, ValueEnum)]
 enum OCROptions {
     Tesseract,
+    /// Tesseract's TSV output, with word confidences.
+    TesseractConfidence,
     Cuneiform,
+    /// Every cell reads its own position, for trying out a grid.
+    Mock,
 }
 
 impl OCROptions {
-    fn cmd_template(&self) -> String {
+    fn backend(&self) -> BackendConfig {
+        let lang = "eng".to_string();
         match self {
-            OCROptions::Tesseract => "tesseract -l eng %img_in% %txt_out%".to_string(),
-            OCROptions::Cuneiform => {
-                "cuneiform -l eng -f text -o %txt_out%.txt %img_in%".to_string()
-            }
+            OCROptions::Tesseract => BackendConfig::default(),
+            OCROptions::TesseractConfidence => BackendConfig::Tesseract(Tesseract {
+                lang,
+                confidence: true,
+            }),
+            OCROptions::Cuneiform => BackendConfig::Cuneiform(Cuneiform { lang }),
+            OCROptions::Mock => BackendConfig::Mock(Mock {
+                text: String::new(),
+            }),
         }
     }
 }
 
+const MAX_ROTATION: f32 = PI / 16.0;
+
+/// Default perspective quad corners (top left, top right, bottom right, bottom
+/// left) in normalized plot coordinates.
+const DEFAULT_CORNERS: [[f64; 2]; 4] = [[0.1, 0.9], [0.9, 0.9], [0.9, 0.1], [0.1, 0.1]];
+
 struct TableImage {
     base: ColorImage,
     rotated: ColorImage,
+    /// Rotated image with the perspective correction applied (if any), which the
+    /// grid is placed on and OCR operates on.
+    rectified: ColorImage,
     theta: f32,
     theta_old: f32,
+    /// Table corners in the rotated image, mapped to a rectangle when correcting
+    /// perspective.
+    corners: [[f64; 2]; 4],
+    perspective: bool,
+    editing_perspective: bool,
     base_tex: Option<TextureHandle>,
     rot_tex: Option<TextureHandle>,
+    rect_tex: Option<TextureHandle>,
 }
 
 impl TableImage {
+    fn new(cim: ColorImage) -> Self {
+        Self {
+            base: cim.clone(),
+            rotated: cim.clone(),
+            rectified: cim,
+            theta: 0.0,
+            theta_old: 0.0,
+            corners: DEFAULT_CORNERS,
+            perspective: false,
+            editing_perspective: false,
+            base_tex: None,
+            rot_tex: None,
+            rect_tex: None,
+        }
+    }
     #[allow(dead_code)]
     fn base_tex(&mut self, ctx: &Context) -> &TextureHandle {
         self.base_tex.get_or_insert_with(|| {
@@ -259,20 +1032,38 @@ impl TableImage {
             ctx.load_texture("test_img", self.rotated.clone(), TextureOptions::LINEAR)
         })
     }
+    fn rect_tex(&mut self, ctx: &Context) -> &TextureHandle {
+        self.rect_tex.get_or_insert_with(|| {
+            ctx.load_texture("test_img", self.rectified.clone(), TextureOptions::LINEAR)
+        })
+    }
     fn inspect_rotation(&mut self, ui: &mut egui::Ui) {
         ui.label("Rotation");
-        ui.add(Slider::new(&mut self.theta, -PI / 16.0..=PI / 16.0));
+        ui.add(Slider::new(&mut self.theta, -MAX_ROTATION..=MAX_ROTATION));
+        let mut degrees = self.theta.to_degrees();
+        if ui
+            .add(DragValue::new(&mut degrees).speed(0.01).suffix("°"))
+            .changed()
+        {
+            self.theta = degrees.to_radians().clamp(-MAX_ROTATION, MAX_ROTATION);
+        }
+        if ui.button("Auto deskew").clicked() {
+            self.theta = detect::estimate_skew(&InkMask::new(&self.base), MAX_ROTATION);
+        }
+        self.update_rotation();
+    }
+    /// Rotates and rectifies the image again after its rotation or perspective
+    /// corners were set.
+    fn refresh(&mut self) {
+        match self.theta != self.theta_old {
+            true => self.update_rotation(),
+            false => self.rectify(),
+        }
+    }
+    fn update_rotation(&mut self) {
         if self.theta != self.theta_old {
-            let base = RgbaImage::from_fn(
-                self.base.width() as u32,
-                self.base.height() as u32,
-                |i, j| {
-                    let color = self.base.pixels[(j as usize) * self.base.width() + (i as usize)];
-                    image::Rgba([color.r(), color.g(), color.b(), color.a()])
-                },
-            );
             let rotated_image = rotate_about_center(
-                &base,
+                &cim_to_rgba(&self.base),
                 self.theta,
                 geometric_transformations::Interpolation::Bicubic,
                 image::Rgba([255, 0, 0, 0]),
@@ -280,8 +1071,89 @@ impl TableImage {
             self.rotated = img_to_cim(rotated_image.into());
             self.theta_old = self.theta;
             self.rot_tex = None;
+            self.rectify();
+        }
+    }
+    /// Maps the corners quad onto the rectangle of its averaged edges.
+    fn warp_perspective(&self) -> Option<ColorImage> {
+        let [w, h] = self.rotated.size.map(|s| s as f32);
+        let from = self
+            .corners
+            .map(|[x, y]| ((x as f32) * w, (1.0 - y as f32) * h));
+        let [tl, tr, br, bl] = from;
+        let (left, right) = ((tl.0 + bl.0) / 2.0, (tr.0 + br.0) / 2.0);
+        let (top, bottom) = ((tl.1 + tr.1) / 2.0, (bl.1 + br.1) / 2.0);
+        let to = [(left, top), (right, top), (right, bottom), (left, bottom)];
+        let projection = Projection::from_control_points(from, to)?;
+        let warped = warp(
+            &cim_to_rgba(&self.rotated),
+            &projection,
+            geometric_transformations::Interpolation::Bicubic,
+            image::Rgba([255, 0, 0, 0]),
+        );
+        Some(img_to_cim(warped.into()))
+    }
+    fn rectify(&mut self) {
+        self.rectified = match self.perspective {
+            true => match self.warp_perspective() {
+                Some(cim) => cim,
+                None => {
+                    self.perspective = false;
+                    self.rotated.clone()
+                }
+            },
+            false => self.rotated.clone(),
+        };
+        self.rect_tex = None;
+    }
+    fn inspect_perspective(&mut self, ui: &mut egui::Ui) {
+        ui.toggle_value(&mut self.editing_perspective, "Edit perspective");
+        if self.editing_perspective && ui.button("Apply perspective").clicked() {
+            self.perspective = true;
+            self.editing_perspective = false;
+            self.rectify();
+        }
+        if self.perspective && ui.button("Clear perspective").clicked() {
+            self.perspective = false;
+            self.corners = DEFAULT_CORNERS;
+            self.rectify();
         }
     }
+    /// Texture of the image being annotated, the rotated image while placing the
+    /// perspective corners and the rectified one otherwise.
+    fn plot_tex(&mut self, ctx: &Context) -> TextureHandle {
+        match self.editing_perspective {
+            true => self.rot_tex(ctx).clone(),
+            false => self.rect_tex(ctx).clone(),
+        }
+    }
+    fn plot_corners(&mut self, pui: &mut PlotUi) {
+        SHARED_STATE.with_borrow_mut(|ss| {
+            pui.polygon(
+                Polygon::new(self.corners.to_vec())
+                    .fill_color(Color32::TRANSPARENT)
+                    .stroke(Stroke::new(2.0, ss.separator_color)),
+            );
+            pui.points(
+                Points::new(self.corners.to_vec())
+                    .radius(6.0)
+                    .color(ss.separator_color),
+            );
+
+            if let Some(pointer) = pui.pointer_coordinate() {
+                let near = self.corners.iter().position(|[x, y]| {
+                    (x - pointer.x).abs() < 2.0 * ss.delta_x
+                        && (y - pointer.y).abs() < 2.0 * ss.delta_y
+                });
+                if let (Some(k), true) = (near, ss.drag_enabled) {
+                    ss.drag_enabled = false;
+                    let dd = pui.pointer_coordinate_drag_delta();
+                    self.corners[k][0] += dd.x as f64;
+                    self.corners[k][1] += dd.y as f64;
+                }
+            }
+        });
+    }
 }
 
 static HELP_STR: &str = "Key bindings for image preview (egui plot).
@@ -298,43 +1170,143 @@ Right click: place new horizontal separator.
 
 Right click + Shift: place new vertical separator.
 
+Right click + Alt: [on separator] add a bend control point, [on control point] remove it. Control points are moved with left click drag.
+
+Edit perspective: left click drag the four corner handles onto the corners of the table, then apply to rectify the image.
+
+Right click + Ctrl: [first and second click] merge cells between the two clicked cells, [on merged cell] unmerge.
+
 
 When finished annotating, hit extract to generate table.";
 
-pub struct TableGrid {
-    image_path: Option<PathBuf>,
-    image: Option<TableImage>,
+/// One of the tables on the image, with its own separators and extraction.
+struct NamedGrid {
+    name: String,
     grid: Grid,
-    cmd_template: String,
     process_task: BackgroundTask<BackgroundOCR>,
+    /// Cleaning of the grid's extractions and retries, apart from the one
+    /// overriding it for re-OCRing selected cells.
+    cleaning_options: CleaningOptions,
+    /// Proofreading of the extracted table.
+    review: Review,
+    /// Set to cancel the running extraction, replaced at each start.
+    cancel: Arc<AtomicBool>,
 }
 
-impl Default for TableGrid {
-    fn default() -> Self {
+impl NamedGrid {
+    fn new(name: String) -> Self {
         Self {
-            image_path: Default::default(),
-            image: Default::default(),
+            name,
             grid: Default::default(),
-            cmd_template: OCROptions::Tesseract.cmd_template(),
             process_task: Default::default(),
+            cleaning_options: Default::default(),
+            review: Default::default(),
+            cancel: Default::default(),
+        }
+    }
+    /// A cancel flag for a new extraction, the previous one's being left set.
+    fn new_cancel(&mut self) -> Arc<AtomicBool> {
+        self.cancel = Default::default();
+        self.cancel.clone()
+    }
+    fn is_extracting(&self) -> bool {
+        matches!(self.process_task, BackgroundTask::Ongoing { .. })
+    }
+    fn table(&self) -> Option<&TableEdit> {
+        match &self.process_task {
+            BackgroundTask::Finished {
+                result: Ok((table, _)),
+                ..
+            } => Some(table),
+            _ => None,
         }
     }
+    /// The extracted table, while the grid still matches its cells.
+    fn matching_table(&self) -> Option<&TableEdit> {
+        self.table().filter(|t| {
+            t.items.len() == self.grid.nrows()
+                && t.items[0].len() == self.grid.ncols()
+                && t.spans == self.grid.spans()
+        })
+    }
 }
 
-fn save_img(buff: &[u8], size: [usize; 2], fpath: impl AsRef<Path>) -> ImageResult<()> {
-    image::save_buffer(
-        fpath,
-        buff,
-        size[0] as u32,
-        size[1] as u32,
-        ColorType::Rgba8,
-    )
+pub struct TableGrid {
+    image: Option<TableImage>,
+    /// Pages the image is one of, with the annotations of the other pages.
+    document: Option<Document>,
+    grids: Vec<NamedGrid>,
+    /// Index of the grid being annotated.
+    selected: usize,
+    /// First corner of a merge being placed.
+    merge_start: Option<[f64; 2]>,
+    line_detection: LineDetection,
+    gap_sensitivity: f64,
+    backend: BackendConfig,
+    /// Keep the files exchanged with the OCR engine, for debugging it.
+    keep_files: bool,
+    /// Seconds the OCR engine may take on a cell before it is killed, 0 for no
+    /// limit.
+    cell_timeout: f32,
+    /// Cells OCRed at once, 0 for one per core.
+    workers: usize,
+    /// Pool every extraction runs on, with the number of workers it was built for.
+    pool: Option<(usize, Arc<ThreadPool>)>,
+    /// Threads of each OCR engine process, 0 for the engine's default.
+    engine_threads: usize,
+    /// Last loaded template, applied to each newly loaded image.
+    loaded_template: Option<Template>,
+    align_template: bool,
+    batch: BatchDialog,
+    /// Header rows of tables continuing over pages, dropped where repeated.
+    header_rows: usize,
+    /// Tables concatenated over the document's pages, by grid name.
+    continued: Vec<(String, TableEdit)>,
+    /// Backend and cleaning overrides for OCRing selected cells again.
+    reocr: ReOcrOptions,
+}
+
+impl Default for TableGrid {
+    fn default() -> Self {
+        Self {
+            image: Default::default(),
+            document: None,
+            grids: vec![NamedGrid::new("Table 1".to_string())],
+            selected: 0,
+            merge_start: None,
+            line_detection: Default::default(),
+            gap_sensitivity: 0.5,
+            backend: Default::default(),
+            keep_files: false,
+            cell_timeout: DEFAULT_CELL_TIMEOUT,
+            workers: 0,
+            pool: None,
+            engine_threads: DEFAULT_ENGINE_THREADS,
+            loaded_template: None,
+            align_template: true,
+            batch: Default::default(),
+            header_rows: 1,
+            continued: vec![],
+            reocr: Default::default(),
+        }
+    }
 }
 
 fn clip(x: f64) -> f64 {
     x.max(0.0).min(1.0)
 }
 
+fn cim_to_rgba(cim: &ColorImage) -> RgbaImage {
+    RgbaImage::from_fn(cim.width() as u32, cim.height() as u32, |i, j| {
+        let color = cim.pixels[(j as usize) * cim.width() + (i as usize)];
+        image::Rgba([color.r(), color.g(), color.b(), color.a()])
+    })
+}
+
+fn open_image(path: impl AsRef<Path>) -> ImageResult<ColorImage> {
+    Ok(img_to_cim(image::ImageReader::open(path)?.decode()?))
+}
+
 fn img_to_cim(image: image::DynamicImage) -> ColorImage {
     let size = [image.width() as _, image.height() as _];
     let image_buffer = image.to_rgba8();
@@ -351,6 +1323,17 @@ struct CleaningOptions {
     no_newlines: bool,
 }
 
+/// Backend and cleaning for OCRing selected cells again, e.g. a column in another
+/// language.
+#[derive(Clone, Default, EguiInspect)]
+#[inspect(collapsible)]
+struct ReOcrOptions {
+    /// Whether to use `backend` rather than the table's.
+    override_backend: bool,
+    backend: BackendConfig,
+    cleaning_options: CleaningOptions,
+}
+
 impl Default for CleaningOptions {
     fn default() -> Self {
         Self {
@@ -363,49 +1346,365 @@ impl Default for CleaningOptions {
 }
 
 impl TableGrid {
-    fn load_image(&mut self) {
-        let img_path = self.image_path.take().unwrap();
-        let image = image::ImageReader::open(img_path)
-            .unwrap()
-            .decode()
-            .unwrap();
-        let cim = img_to_cim(image);
-
-        self.image = Some(TableImage {
-            base: cim.clone(),
-            rotated: cim,
-            theta: 0.0,
-            theta_old: 0.0,
-            base_tex: None,
-            rot_tex: None,
-        });
+    fn load_document(&mut self, paths: Vec<PathBuf>) {
+        // the current document and its annotation stay until the first page renders
+        let first_page = Document::open(&paths)
+            .and_then(|document| document.render(0).map(|cim| (document, cim)));
+        match first_page {
+            Ok((document, cim)) => {
+                // the grids carry over to the first page, as when loading an image
+                self.image = None;
+                self.show_rendered(document, 0, cim);
+            }
+            Err(e) => report_error(e),
+        }
+    }
+    /// Switches to page `k` of the document, keeping the annotation of the current
+    /// page. Pages visited for the first time start from the grids of the page left
+    /// (when reused) or the loaded template.
+    fn show_page(&mut self, k: usize) {
+        let Some(document) = self.document.take() else {
+            return;
+        };
+        match document.render(k) {
+            Ok(cim) => self.show_rendered(document, k, cim),
+            Err(e) => {
+                report_error(e);
+                self.document = Some(document);
+            }
+        }
+    }
+    /// Shows `cim`, rendered from page `k` of `document`, as in [`Self::show_page`].
+    fn show_rendered(&mut self, mut document: Document, k: usize, cim: ColorImage) {
+        if let Some(image) = self.image.take() {
+            document.pages[document.current].state = Some(PageState {
+                theta: image.theta,
+                corners: image.corners,
+                perspective: image.perspective,
+                grids: std::mem::take(&mut self.grids),
+                selected: self.selected,
+            });
+        }
+        // the page last worked on, whose grids new pages may start from
+        let left = std::mem::replace(&mut document.current, k);
+        self.merge_start = None;
+
+        match document.pages[k].state.take() {
+            Some(state) => {
+                let mut image = TableImage {
+                    theta: state.theta,
+                    corners: state.corners,
+                    perspective: state.perspective,
+                    ..TableImage::new(cim)
+                };
+                image.refresh();
+                self.image = Some(image);
+                self.grids = state.grids;
+                self.selected = state.selected;
+            }
+            None => {
+                let previous = document.pages[left].state.as_ref();
+                match previous.filter(|_| document.reuse_grid) {
+                    // placed as on that page, rather than registered from the template
+                    Some(previous) => {
+                        self.image = Some(TableImage::new(cim));
+                        self.grids = previous
+                            .grids
+                            .iter()
+                            .map(|named| NamedGrid {
+                                grid: named.grid.clone(),
+                                cleaning_options: named.cleaning_options,
+                                ..NamedGrid::new(named.name.clone())
+                            })
+                            .collect();
+                        self.selected = previous.selected;
+                    }
+                    None => {
+                        if self.grids.is_empty() {
+                            self.grids = vec![NamedGrid::new("Table 1".to_string())];
+                            self.selected = 0;
+                        }
+                        self.set_image(cim);
+                    }
+                }
+            }
+        }
+        self.document = Some(document);
+    }
+    fn set_image(&mut self, cim: ColorImage) {
+        self.image = Some(TableImage::new(cim));
+        self.apply_template_layout();
+    }
+    fn grid(&mut self) -> &mut Grid {
+        &mut self.grids[self.selected].grid
     }
     fn update_extents(&self) {
         SHARED_STATE.with_borrow_mut(|ss| {
-            ss.extents = Extents {
-                xmin: self.grid.verticals.first().unwrap().x,
-                xmax: self.grid.verticals.last().unwrap().x,
-                ymin: self.grid.horizontals.first().unwrap().y,
-                ymax: self.grid.horizontals.last().unwrap().y,
-            };
+            ss.extents = self.grids[self.selected].grid.extents();
+        })
+    }
+    fn template(&self) -> Template {
+        Template {
+            theta: self.image.as_ref().map_or(0.0, |img| img.theta),
+            corners: self
+                .image
+                .as_ref()
+                .and_then(|img| img.perspective.then_some(img.corners)),
+            reference: self
+                .image
+                .as_ref()
+                .map(|img| Reference::new(&InkMask::new(&img.base), &InkMask::new(&img.rectified))),
+            backend: self.backend.clone(),
+            grids: self
+                .grids
+                .iter()
+                .map(|named| TemplateGrid {
+                    name: named.name.clone(),
+                    grid: named.grid.clone(),
+                })
+                .collect(),
+        }
+    }
+    /// Applies the loaded template as the user loads or reapplies it, backend
+    /// included. New images only get its layout, keeping any backend picked since.
+    fn apply_template(&mut self) {
+        let Some(template) = &self.loaded_template else {
+            return;
+        };
+        self.backend = template.backend.clone();
+        self.apply_template_layout();
+    }
+    /// Applies the loaded template's rotation, perspective and grids, registered onto
+    /// the current image when it has a reference and alignment is enabled.
+    fn apply_template_layout(&mut self) {
+        let Some(template) = self.loaded_template.clone() else {
+            return;
+        };
+        let mut grids = template.grids;
+        if let Some(image) = self.image.as_mut() {
+            image.theta = template.theta;
+            image.corners = template.corners.unwrap_or(DEFAULT_CORNERS);
+            image.perspective = template.corners.is_some();
+            if let (Some(reference), true) = (&template.reference, self.align_template) {
+                let level_theta = detect::estimate_skew(&InkMask::new(&image.base), MAX_ROTATION);
+                image.theta = (template.theta + level_theta - reference.level_theta)
+                    .clamp(-MAX_ROTATION, MAX_ROTATION);
+                image.refresh();
+
+                let mask = InkMask::new(&image.rectified);
+                let (cols, rows) = detect::ink_profiles(&mask, PROFILE_BINS);
+                let (sx, tx) = detect::align_profiles(&reference.cols, &cols);
+                let (sy, ty) = detect::align_profiles(&reference.rows, &rows);
+                for tg in grids.iter_mut() {
+                    tg.grid.transform(sx, tx, sy, ty);
+                }
+            } else {
+                image.refresh();
+            }
+        }
+        if !grids.is_empty() {
+            self.grids = grids
+                .into_iter()
+                .map(|tg| NamedGrid {
+                    grid: tg.grid,
+                    ..NamedGrid::new(tg.name)
+                })
+                .collect();
+            self.selected = 0;
+            self.merge_start = None;
+        }
+    }
+    fn inspect_template(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Save template").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_directory(".")
+                .add_filter("Grid template", &["json"])
+                .save_file()
+            {
+                if let Err(e) = self.template().save(path) {
+                    report_error(e);
+                }
+            }
+        }
+        ui.checkbox(&mut self.align_template, "Align to template");
+        if self.loaded_template.is_some() && ui.button("Reapply template").clicked() {
+            self.apply_template();
+        }
+        if ui.button("Load template").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_directory(".")
+                .add_filter("Grid template", &["json"])
+                .pick_file()
+            {
+                match Template::load(path) {
+                    Ok(template) => {
+                        self.loaded_template = Some(template);
+                        self.apply_template();
+                    }
+                    Err(e) => report_error(e),
+                }
+            }
+        }
+    }
+    /// The current annotation as a batch extraction, or the loaded template before
+    /// any image.
+    fn batch_extraction(&self) -> Option<Extraction> {
+        let template = match self.image {
+            Some(_) => self.template(),
+            None => self.loaded_template.clone()?,
+        };
+        Some(Extraction {
+            template,
+            backend: self.backend.clone(),
+            align: self.align_template,
+            cleaning_options: Default::default(),
+            keep_files: self.keep_files,
+            timeout: backend::timeout(self.cell_timeout),
+            cancel: Default::default(),
+            pool: self.pool.as_ref().map(|(_, pool)| pool.clone()),
+            engine_threads: backend::engine_threads(self.engine_threads),
         })
     }
+    /// Each grid's extracted tables over the document's pages, concatenated.
+    fn continued_tables(&self) -> Vec<(String, TableEdit)> {
+        let Some(document) = &self.document else {
+            return vec![];
+        };
+        let page_grids: Vec<&[NamedGrid]> = (0..document.pages.len())
+            .filter_map(|k| match k == document.current {
+                true => Some(&self.grids[..]),
+                false => document.pages[k].state.as_ref().map(|s| &s.grids[..]),
+            })
+            .collect();
+        let names: Vec<&str> = page_grids
+            .iter()
+            .flat_map(|grids| grids.iter().map(|g| g.name.as_str()))
+            .unique()
+            .collect();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let tables: Vec<_> = page_grids
+                    .iter()
+                    .filter_map(|grids| grids.iter().find(|g| g.name == name)?.table())
+                    .collect();
+                (!tables.is_empty()).then(|| {
+                    (
+                        name.to_string(),
+                        continued::concat(&tables, self.header_rows),
+                    )
+                })
+            })
+            .collect()
+    }
+    fn inspect_continued(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Continued tables");
+        ui.horizontal(|ui| {
+            ui.label("Header rows");
+            ui.add(DragValue::new(&mut self.header_rows).range(0..=10));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Continue tables").clicked() {
+                self.continued = self.continued_tables();
+            }
+            if !self.continued.is_empty() {
+                if ui.button("Export").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_directory(".")
+                        .add_filter("Table", &["csv", "html", "xlsx"])
+                        .save_file()
+                    {
+                        if let Err(e) = export::write_tables(&self.continued, &path) {
+                            report_error(e);
+                        }
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    self.continued.clear();
+                }
+            }
+        });
+    }
+    fn new_grid(&mut self) {
+        let name = (1..)
+            .map(|k| format!("Table {k}"))
+            .find(|name| self.grids.iter().all(|g| &g.name != name))
+            .unwrap();
+        self.grids.push(NamedGrid::new(name));
+        self.selected = self.grids.len() - 1;
+    }
+    fn inspect_grids(&mut self, ui: &mut egui::Ui) {
+        let selected = self.selected;
+        ui.label("Grid");
+        ComboBox::from_id_source("grid_select")
+            .selected_text(self.grids[self.selected].name.as_str())
+            .show_ui(ui, |ui| {
+                for (k, named) in self.grids.iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, k, named.name.as_str());
+                }
+            });
+        ui.add(TextEdit::singleline(&mut self.grids[self.selected].name).desired_width(100.0));
+        if ui.button("New grid").clicked() {
+            self.new_grid();
+        }
+        if ui.button("Delete grid").clicked() && self.grids.len() > 1 {
+            self.grids.remove(self.selected);
+            self.selected = self.selected.min(self.grids.len() - 1);
+        }
+        if self.selected != selected {
+            self.merge_start = None;
+        }
+        if ui.button("Export workbook").clicked() {
+            let tables: Vec<_> = self
+                .grids
+                .iter()
+                .filter_map(|g| Some((g.name.as_str(), g.table()?)))
+                .collect();
+            if let Some(path) = rfd::FileDialog::new()
+                .set_directory(".")
+                .add_filter("Excel workbook", &["xlsx"])
+                .save_file()
+            {
+                if let Err(e) = export::xlsx_workbook(&tables, &path).at(&path) {
+                    report_error(e);
+                }
+            }
+        }
+    }
 }
 
-fn crop_buffer(cim: &ColorImage, x1: f64, x2: f64, y1: f64, y2: f64) -> (Vec<u8>, [usize; 2]) {
-    let orig_size = cim.size;
-    let i0 = (clip(x1.min(x2)) * (orig_size[0] as f64)) as usize;
-    let i1 = (clip(x1.max(x2)) * (orig_size[0] as f64)) as usize;
-    let j0 = (clip(1.0 - y1.max(y2)) * (orig_size[1] as f64)) as usize;
-    let j1 = (clip(1.0 - y1.min(y2)) * (orig_size[1] as f64)) as usize;
-    let size = [i1 - i0, j1 - j0];
-    let mut out = vec![];
+/// Crops a (possibly merged) cell, the region enclosed by its (possibly bent)
+/// separators, with the pixels of its bounding box outside of it masked white.
+/// Expects a sorted grid.
+fn crop_cell(cim: &ColorImage, grid: &Grid, cell: &CellSpan) -> (Vec<u8>, [usize; 2]) {
+    let top = grid.horizontals.len() - 1 - cell.row;
+    let (left, right) = (
+        &grid.verticals[cell.col],
+        &grid.verticals[cell.col + cell.colspan],
+    );
+    let (lower, upper) = (
+        &grid.horizontals[top - cell.rowspan],
+        &grid.horizontals[top],
+    );
+
+    let [width, height] = cim.size;
+    let i0 = (clip(left.x_range().0) * (width as f64)) as usize;
+    let i1 = (clip(right.x_range().1) * (width as f64)) as usize;
+    let j0 = (clip(1.0 - upper.y_range().1) * (height as f64)) as usize;
+    let j1 = (clip(1.0 - lower.y_range().0) * (height as f64)) as usize;
+    let size = [i1.saturating_sub(i0), j1.saturating_sub(j0)];
+    let mut out = Vec::with_capacity(4 * size[0] * size[1]);
     for j in j0..j1 {
+        let y = detect::norm_y(j as f64, height);
         for i in i0..i1 {
-            out.push(cim.pixels[j * orig_size[0] + i].r());
-            out.push(cim.pixels[j * orig_size[0] + i].g());
-            out.push(cim.pixels[j * orig_size[0] + i].b());
-            out.push(cim.pixels[j * orig_size[0] + i].a());
+            let x = detect::norm_x(i as f64, width);
+            let inside =
+                left.x_at(y) <= x && x < right.x_at(y) && lower.y_at(x) <= y && y < upper.y_at(x);
+            let color = match inside {
+                true => cim.pixels[j * width + i],
+                false => Color32::WHITE,
+            };
+            out.extend_from_slice(&color.to_array());
         }
     }
     (out, size)
@@ -418,107 +1717,262 @@ struct BackgroundOCR {
     #[inspect(hide)]
     cim: ColorImage,
     #[inspect(hide)]
-    cmd_template: String,
+    backend: BackendConfig,
+    #[inspect(hide)]
+    keep_files: bool,
+    #[inspect(hide)]
+    timeout: Option<Duration>,
+    /// Shared with the grid's Cancel button.
+    #[inspect(hide)]
+    cancel: Arc<AtomicBool>,
+    /// Pool shared by the app's extractions, bounding the cells OCRed at once
+    /// across them, the global pool when `None`.
+    #[inspect(hide)]
+    pool: Option<Arc<ThreadPool>>,
+    #[inspect(hide)]
+    engine_threads: Option<usize>,
+    /// Resolution the page was rendered at, for PDF pages.
+    #[inspect(hide)]
+    dpi: Option<f32>,
     #[inspect(hide)]
     ready: bool,
     #[inspect(hide)]
     n_tasks: usize,
+    /// Cells to OCR, every cell of the grid when `None`.
+    #[inspect(hide)]
+    cells: Option<Vec<(usize, usize)>>,
+    /// Table the OCRed cells are merged into, when only some are.
+    #[inspect(hide)]
+    previous: Option<TableEdit>,
+    #[inspect(hide)]
     cleaning_options: CleaningOptions,
 }
 
 impl Task for BackgroundOCR {
-    type Return = TableEdit;
+    type Return = (TableEdit, Vec<Error>);
 
     fn exec_with_expected_steps(&self) -> Option<usize> {
         self.ready.then_some(self.n_tasks)
     }
 
     fn on_exec(&mut self, progress: egui_inspect::background_task::Progress) -> Self::Return {
-        let mut items = vec![
-            vec![String::new(); self.grid.verticals.len() - 1];
-            self.grid.horizontals.len() - 1
-        ];
-        let co = self.cleaning_options;
+        self.extract(|| progress.increment())
+    }
+}
 
-        let out_flat: Vec<io::Result<_>> = self
-            .grid
-            .horizontals
-            .windows(2)
-            .rev()
-            .enumerate()
-            .cartesian_product(self.grid.verticals.windows(2).enumerate())
-            .par_bridge()
-            .map(|((i, hw), (j, vw))| {
-                let img_path = format!("/tmp/ocr_crop_{i}_{j}.png");
-                let txt_path = format!("/tmp/ocr_out_{i}_{j}");
-
-                let (buff, size) = crop_buffer(&self.cim, vw[0].x, vw[1].x, hw[0].y, hw[1].y);
-                save_img(buff.as_slice(), size, Path::new(img_path.as_str())).unwrap();
-
-                let cmd = self
-                    .cmd_template
-                    .as_str()
-                    .replace("%img_in%", img_path.as_str())
-                    .replace("%txt_out%", txt_path.as_str());
-
-                let mut cmd_iter = cmd.split_whitespace();
-
-                let prog = cmd_iter.next().unwrap();
-                let mut handle = Command::new(prog)
-                    .args(cmd_iter)
-                    .stdout(Stdio::null())
-                    .stderr(Stdio::null())
-                    .spawn()?;
-
-                handle.wait()?;
-
-                let txt_path = format!("{txt_path}.txt");
-                let mut ocr_out = fs::read_to_string(txt_path.as_str())?;
-                fs::remove_file(img_path.as_str())?;
-                fs::remove_file(txt_path.as_str())?;
-
-                if co.trim_whitespace {
-                    ocr_out = ocr_out.trim().to_string();
-                }
-                if co.trim_single_quote {
-                    ocr_out = ocr_out.trim_matches('\'').trim_matches('‘').to_string();
-                }
-                if co.trim_double_quote {
-                    ocr_out = ocr_out.trim_matches('"').to_string();
-                }
-                if co.no_newlines {
-                    ocr_out = ocr_out.replace('\n', "").to_string();
-                }
+impl BackgroundOCR {
+    fn new(grid: Grid, cim: ColorImage, backend: BackendConfig) -> Self {
+        Self {
+            n_tasks: grid.cells().len(),
+            grid,
+            cim,
+            backend,
+            ..Default::default()
+        }
+    }
 
-                progress.increment();
+    /// OCRs only `cells` again, keeping the other cells of `previous`.
+    fn retry(self, cells: Vec<(usize, usize)>, previous: TableEdit) -> Self {
+        Self {
+            n_tasks: cells.len(),
+            cells: Some(cells),
+            previous: Some(previous),
+            ..self
+        }
+    }
 
-                Ok((i, j, ocr_out))
-            })
-            .collect();
+    /// OCRs a single cell through the backend, cleaning its text.
+    fn ocr_cell(&self, cell: &CellSpan, run: &RunContext) -> error::Result<Recognition> {
+        if run.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let (buff, size) = crop_cell(&self.cim, &self.grid, cell);
+        let image =
+            RgbaImage::from_raw(size[0] as u32, size[1] as u32, buff).ok_or("invalid cell crop")?;
+        let mut recognition = self.backend.backend().recognize(&image, cell, run)?;
 
-        for res in out_flat {
+        let mut ocr_out = std::mem::take(&mut recognition.text);
+        let co = self.cleaning_options;
+        if co.trim_whitespace {
+            ocr_out = ocr_out.trim().to_string();
+        }
+        if co.trim_single_quote {
+            ocr_out = ocr_out.trim_matches('\'').trim_matches('‘').to_string();
+        }
+        if co.trim_double_quote {
+            ocr_out = ocr_out.trim_matches('"').to_string();
+        }
+        if co.no_newlines {
+            ocr_out = ocr_out.replace('\n', "").to_string();
+        }
+        recognition.text = ocr_out;
+        Ok(recognition)
+    }
+
+    /// OCRs the cells in parallel, calling `on_cell` as each is done. Cells that
+    /// failed are marked so, keeping the text of an earlier run (if any), with
+    /// their errors returned alongside. Cells not done when cancelled are marked
+    /// failed too, so that retrying the failed cells resumes the extraction.
+    fn extract(&self, on_cell: impl Fn() + Sync) -> (TableEdit, Vec<Error>) {
+        let mut table = match &self.previous {
+            Some(t) => t.clone(),
+            None => TableEdit::empty(self.grid.nrows(), self.grid.ncols()),
+        };
+
+        let mut cells = self.grid.cells();
+        if let Some(subset) = &self.cells {
+            cells.retain(|cell| subset.contains(&(cell.row, cell.col)));
+        }
+        // its workspace removed once every cell is done
+        let run = Workspace::new(self.keep_files).map(|workspace| RunContext {
+            workspace,
+            timeout: self.timeout,
+            cancel: self.cancel.clone(),
+            engine_threads: self.engine_threads,
+            dpi: self.dpi,
+        });
+        let ocr_cells = || -> Vec<_> {
+            cells
+                .par_iter()
+                .map(|cell| {
+                    let res = match &run {
+                        Ok(run) => self.ocr_cell(cell, run),
+                        Err(e) => Err(Error::Invalid(e.to_string())),
+                    };
+                    on_cell();
+                    (cell.row, cell.col, res)
+                })
+                .collect()
+        };
+        let out_flat = match &self.pool {
+            Some(pool) => pool.install(ocr_cells),
+            None => ocr_cells(),
+        };
+
+        let mut errors = vec![];
+        let mut cancelled = 0;
+        for (i, j, res) in out_flat {
             match res {
-                Ok((i, j, s)) => items[i][j] = s,
+                Err(Error::Cancelled) => {
+                    table.status[i][j] = CellStatus::Failed(Error::Cancelled.to_string());
+                    cancelled += 1;
+                }
+                Ok(recognition) => {
+                    table.status[i][j] = match recognition.text.is_empty() {
+                        true => CellStatus::Empty,
+                        false => CellStatus::Ok,
+                    };
+                    table.items[i][j] = recognition.text;
+                    table.confidence[i][j] = recognition.confidence;
+                }
                 Err(e) => {
-                    dbg!(e);
+                    table.status[i][j] = CellStatus::Failed(e.to_string());
+                    // grouped by message, e.g. a missing OCR program failing every cell
+                    let message = e.to_string();
+                    match errors.iter_mut().find(|(m, _)| *m == message) {
+                        Some((_, Error::Cells { cells, .. })) => cells.push((i, j)),
+                        _ => errors.push((
+                            message,
+                            Error::Cells {
+                                cells: vec![(i, j)],
+                                source: Box::new(e),
+                            },
+                        )),
+                    }
                 }
             }
         }
 
-        TableEdit { items }
+        let mut errors: Vec<_> = errors.into_iter().map(|(_, e)| e).collect();
+        if cancelled > 0 {
+            errors.push(format!("cancelled, {cancelled} cell(s) not done").into());
+        }
+
+        table.spans = self.grid.spans();
+        table.kept_files = run
+            .as_ref()
+            .ok()
+            .and_then(|run| run.workspace.kept_dir())
+            .map(Path::to_path_buf);
+        (table, errors)
     }
 }
 
+/// Pool of `workers` threads (one per core for 0) for OCRing cells, shared by the
+/// extractions running at once.
+fn worker_pool(workers: usize) -> error::Result<Arc<ThreadPool>> {
+    ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .thread_name(|k| format!("ocr-worker-{k}"))
+        .build()
+        .map(Arc::new)
+        .map_err(|e| Error::Invalid(format!("could not start {workers} OCR workers, {e}")))
+}
+
 impl egui_inspect::eframe::App for TableGrid {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut egui_inspect::eframe::Frame) {
+        // rebuilt when the number of workers changes, extractions still running
+        // keeping the previous pool until they finish
+        if self.pool.as_ref().map(|(workers, _)| *workers) != Some(self.workers) {
+            match worker_pool(self.workers) {
+                Ok(pool) => self.pool = Some((self.workers, pool)),
+                Err(e) => {
+                    report_error(e);
+                    self.workers = self.pool.as_ref().map_or(0, |(workers, _)| *workers);
+                }
+            }
+        }
+        let has_errors = SHARED_STATE.with_borrow(|ss| !ss.errors.is_empty());
+        if has_errors {
+            TopBottomPanel::bottom("errors").show(ctx, |ui| {
+                SHARED_STATE.with_borrow_mut(|ss| {
+                    ui.horizontal(|ui| {
+                        ui.heading("Errors");
+                        if ui.button("Dismiss").clicked() {
+                            ss.errors.clear();
+                        }
+                    });
+                    ScrollArea::vertical().max_height(120.0).show(ui, |ui| {
+                        for (message, count) in ss.errors.iter() {
+                            let text = match count {
+                                1 => message.clone(),
+                                n => format!("{message} (×{n})"),
+                            };
+                            ui.colored_label(Color32::RED, text);
+                        }
+                    });
+                });
+            });
+        }
+        if self.document.as_ref().is_some_and(|d| d.is_multipage()) {
+            SidePanel::left("pages").show(ctx, |ui| {
+                let picked = self.document.as_mut().unwrap().inspect(ui);
+                ui.separator();
+                self.inspect_continued(ui);
+                if let Some(k) = picked {
+                    self.show_page(k);
+                }
+            });
+        }
         CentralPanel::default().show(ctx, |ui| {
-            if ui.button("Select table image").clicked() {
-                self.image_path = rfd::FileDialog::new().set_directory(".").pick_file();
-                self.load_image();
+            if ui.button("Select table images").clicked() {
+                if let Some(paths) = rfd::FileDialog::new().set_directory(".").pick_files() {
+                    self.load_document(paths);
+                }
+            }
+            if ui.button("Batch").clicked() {
+                self.batch.open = true;
+            }
+            if self.batch.open {
+                let mut batch = std::mem::take(&mut self.batch);
+                batch.show(ctx, || self.batch_extraction());
+                self.batch = batch;
             }
             if self.image.is_some() {
-                self.grid.sort_vert();
-                self.grid.sort_horiz();
+                for named in self.grids.iter_mut() {
+                    named.grid.sort_vert();
+                    named.grid.sort_horiz();
+                }
 
                 self.update_extents();
 
@@ -531,6 +1985,7 @@ impl egui_inspect::eframe::App for TableGrid {
                         });
 
                         self.image.as_mut().unwrap().inspect_rotation(ui);
+                        self.image.as_mut().unwrap().inspect_perspective(ui);
 
                         SHARED_STATE.with_borrow_mut(|ss| {
                             ui.label("Separator thickness");
@@ -539,71 +1994,240 @@ impl egui_inspect::eframe::App for TableGrid {
                         });
 
                         if ui.button("Remove horiz").clicked() {
-                            if self.grid.horizontals.len() > 2 {
-                                self.grid.horizontals.remove(0);
+                            if self.grid().horizontals.len() > 2 {
+                                self.grid().horizontals.remove(0);
                             }
                         }
                         if ui.button("Remove vert").clicked() {
-                            if self.grid.verticals.len() > 2 {
-                                self.grid.verticals.pop();
+                            if self.grid().verticals.len() > 2 {
+                                self.grid().verticals.pop();
                             }
                         }
                         if ui.button("Reset grid").clicked() {
-                            self.grid = Default::default();
+                            *self.grid() = Default::default();
+                            self.merge_start = None;
                         }
                     });
                     ui.horizontal(|ui| {
-                        self.cmd_template.inspect_mut("command", ui);
-                        ui.menu_button("Preset commands", |ui| {
-                            if ui.button("Tessseract").clicked() {
-                                self.cmd_template = OCROptions::Tesseract.cmd_template();
-                                ui.close_menu();
-                            }
-                            if ui.button("Cuneiform").clicked() {
-                                self.cmd_template = OCROptions::Cuneiform.cmd_template();
-                                ui.close_menu();
-                            }
-                        });
+                        self.inspect_grids(ui);
+                        self.inspect_template(ui);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Detect lines").clicked() {
+                            let mask = InkMask::new(&self.image.as_ref().unwrap().rectified);
+                            let (ys, xs) = detect::ruling_lines(&mask, self.line_detection);
+                            self.grid().set_detected(ys, xs);
+                        }
+                        ui.label("Min line length");
+                        ui.add(Slider::new(&mut self.line_detection.min_length, 0.05..=1.0));
+                        ui.label("Max line gap");
+                        ui.add(Slider::new(&mut self.line_detection.max_gap, 0..=10));
+                        if ui.button("Detect gaps").clicked() {
+                            let mask = InkMask::new(&self.image.as_ref().unwrap().rectified);
+                            let Extents {
+                                xmin,
+                                xmax,
+                                ymin,
+                                ymax,
+                            } = self.grid().extents();
+                            let (ys, xs) = detect::whitespace_gaps(
+                                &mask,
+                                [xmin, xmax, ymin, ymax],
+                                self.gap_sensitivity,
+                            );
+                            self.grid().set_interior(ys, xs);
+                        }
+                        ui.label("Gap sensitivity");
+                        ui.add(Slider::new(&mut self.gap_sensitivity, 0.0..=1.0));
+                    });
+                    ui.horizontal(|ui| {
+                        self.backend.inspect_mut("OCR backend", ui);
+                        ui.checkbox(&mut self.keep_files, "Keep intermediate files");
+                        ui.label("Cell timeout");
+                        ui.add(
+                            DragValue::new(&mut self.cell_timeout)
+                                .range(0.0..=3600.0)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("0 for no limit");
+                        ui.label("Workers");
+                        ui.add(DragValue::new(&mut self.workers).range(0..=256))
+                            .on_hover_text("Cells OCRed at once, 0 for one per core");
+                        ui.label("Engine threads");
+                        ui.add(DragValue::new(&mut self.engine_threads).range(0..=64))
+                            .on_hover_text("OMP_THREAD_LIMIT of each OCR process, 0 for no limit");
                     });
 
-                    self.process_task.inspect_mut("", ui);
-                    let ongoing = match &self.process_task {
-                        BackgroundTask::Ongoing { .. } => true,
-                        _ => false,
-                    };
-                    if !ongoing {
-                        if ui.button("Extract").clicked() {
-                            if let BackgroundTask::Starting { task }
-                            | BackgroundTask::Finished { task, .. } = &mut self.process_task
+                    let timeout = backend::timeout(self.cell_timeout);
+                    let pool = self.pool.as_ref().map(|(_, pool)| pool.clone());
+                    let engine_threads = backend::engine_threads(self.engine_threads);
+                    let dpi = self.document.as_ref().and_then(Document::current_dpi);
+                    for (k, named) in self.grids.iter_mut().enumerate() {
+                        let ongoing = named.is_extracting();
+                        // other grids' extractions are shown while running
+                        if k != self.selected && ongoing {
+                            ui.label(named.name.as_str());
+                        }
+                        if k == self.selected || ongoing {
+                            named.process_task.inspect_mut("", ui);
+                        }
+                        if ongoing && ui.button("Cancel").clicked() {
+                            named.cancel.store(true, Ordering::Relaxed);
+                        }
+                        if k == self.selected && !ongoing {
+                            named.cleaning_options.inspect_mut("Cleaning options", ui);
+                            if ui.button("Extract").clicked() {
+                                let cancel = named.new_cancel();
+                                if let BackgroundTask::Starting { task }
+                                | BackgroundTask::Finished { task, .. } = &mut named.process_task
+                                {
+                                    *task = BackgroundOCR {
+                                        cleaning_options: named.cleaning_options,
+                                        keep_files: self.keep_files,
+                                        timeout,
+                                        cancel,
+                                        pool: pool.clone(),
+                                        engine_threads,
+                                        dpi,
+                                        ready: true,
+                                        ..BackgroundOCR::new(
+                                            named.grid.clone(),
+                                            self.image.as_ref().unwrap().rectified.clone(),
+                                            self.backend.clone(),
+                                        )
+                                    };
+                                }
+                            }
+                            let failed =
+                                named.matching_table().map_or(vec![], |t| t.failed_cells());
+                            if !failed.is_empty()
+                                && ui
+                                    .button(format!("Retry {} failed cell(s)", failed.len()))
+                                    .clicked()
                             {
-                                task.grid = self.grid.clone();
-                                task.cim = self.image.as_ref().unwrap().rotated.clone();
-                                task.cmd_template = self.cmd_template.clone();
-                                task.n_tasks = (self.grid.horizontals.len() - 1)
-                                    * (self.grid.verticals.len() - 1);
-                                task.ready = true;
+                                let cancel = named.new_cancel();
+                                if let BackgroundTask::Finished {
+                                    task,
+                                    result: Ok((table, _)),
+                                } = &mut named.process_task
+                                {
+                                    *task = BackgroundOCR {
+                                        cleaning_options: named.cleaning_options,
+                                        keep_files: self.keep_files,
+                                        timeout,
+                                        cancel,
+                                        pool: pool.clone(),
+                                        engine_threads,
+                                        dpi,
+                                        ready: true,
+                                        ..BackgroundOCR::new(
+                                            named.grid.clone(),
+                                            self.image.as_ref().unwrap().rectified.clone(),
+                                            self.backend.clone(),
+                                        )
+                                    }
+                                    .retry(failed, table.clone());
+                                }
+                            }
+                            if named.matching_table().is_some() && ui.button("Review").clicked() {
+                                if let BackgroundTask::Finished {
+                                    result: Ok((table, _)),
+                                    ..
+                                } = &named.process_task
+                                {
+                                    named.review.start(table);
+                                }
+                            }
+                            let selection = named
+                                .matching_table()
+                                .map_or(vec![], |t| t.selected_cells());
+                            if !selection.is_empty() {
+                                self.reocr.inspect_mut("Re-OCR options", ui);
+                                if ui
+                                    .button(format!("Re-OCR {} selected cell(s)", selection.len()))
+                                    .clicked()
+                                {
+                                    let cancel = named.new_cancel();
+                                    if let BackgroundTask::Finished {
+                                        task,
+                                        result: Ok((table, _)),
+                                    } = &mut named.process_task
+                                    {
+                                        let backend = match self.reocr.override_backend {
+                                            true => self.reocr.backend.clone(),
+                                            false => self.backend.clone(),
+                                        };
+                                        *task = BackgroundOCR {
+                                            cleaning_options: self.reocr.cleaning_options,
+                                            keep_files: self.keep_files,
+                                            timeout,
+                                            cancel,
+                                            pool: pool.clone(),
+                                            engine_threads,
+                                            dpi,
+                                            ready: true,
+                                            ..BackgroundOCR::new(
+                                                named.grid.clone(),
+                                                self.image.as_ref().unwrap().rectified.clone(),
+                                                backend,
+                                            )
+                                        }
+                                        .retry(selection, table.clone());
+                                    }
+                                }
                             }
                         }
-                    }
 
-                    if let BackgroundTask::Finished {
-                        result: Ok(table), ..
-                    } = &mut self.process_task
-                    {
-                        table.inspect_mut("", ui);
+                        // the grid was edited since, its cells no longer match the table's
+                        if named.matching_table().is_none() {
+                            named.review.open = false;
+                        }
+                        if let BackgroundTask::Finished {
+                            result: Ok((table, errors)),
+                            ..
+                        } = &mut named.process_task
+                        {
+                            for e in errors.drain(..) {
+                                report_error(format!("{}: {e}", named.name));
+                            }
+                            let id = Id::new(("table", k));
+                            table.show(id, named.name.as_str(), ui);
+                            if named.review.open {
+                                named.review.show(
+                                    ui.ctx(),
+                                    id,
+                                    named.name.as_str(),
+                                    table,
+                                    &named.grid,
+                                    &self.image.as_ref().unwrap().rectified,
+                                );
+                            }
+                        }
+                    }
+                    for (k, (name, table)) in self.continued.iter_mut().enumerate() {
+                        let label = format!("{name} (continued)");
+                        table.show(Id::new(("continued", k)), label.as_str(), ui);
                     }
 
                     let middle_held =
                         ui.input(|r| r.pointer.button_down(egui::PointerButton::Middle));
                     let zooming =
                         ui.input(|r| r.pointer.button_down(egui::PointerButton::Secondary));
-                    let (new_horiz, new_vert) = ui.input(|r| {
+                    let (new_horiz, new_vert, merge_click, bend_click) = ui.input(|r| {
                         let sec = r.pointer.button_clicked(egui::PointerButton::Secondary);
                         let shif = r.modifiers.shift;
-                        (sec && !shif, sec && shif)
+                        let ctrl = r.modifiers.ctrl;
+                        let alt = r.modifiers.alt;
+                        (
+                            sec && !shif && !ctrl && !alt,
+                            sec && shif && !ctrl && !alt,
+                            sec && ctrl && !alt,
+                            sec && alt,
+                        )
                     });
 
-                    let texture = self.image.as_mut().unwrap().rot_tex(ui.ctx());
+                    let texture = self.image.as_mut().unwrap().plot_tex(ui.ctx());
+                    let editing_perspective = self.image.as_ref().unwrap().editing_perspective;
                     let mut drag_enabled = SHARED_STATE.with_borrow(|ss| ss.drag_enabled);
 
                     Plot::new("plot")
@@ -615,43 +2239,68 @@ impl egui_inspect::eframe::App for TableGrid {
                         .data_aspect(1.0 / texture.aspect_ratio())
                         .show(ui, |pui| {
                             if let Some(pointer) = pui.pointer_coordinate() {
-                                if 0.0 < pointer.x
+                                if !editing_perspective
+                                    && 0.0 < pointer.x
                                     && pointer.x < 1.0
                                     && 0.0 < pointer.y
                                     && pointer.y < 1.0
                                 {
                                     if new_horiz {
-                                        self.grid.horizontals.push(HorizSep { y: pointer.y });
+                                        self.grid().horizontals.push(HorizSep::new(pointer.y));
                                     }
                                     if new_vert {
-                                        self.grid.verticals.push(VertSep { x: pointer.x });
+                                        self.grid().verticals.push(VertSep::new(pointer.x));
+                                    }
+                                    if bend_click {
+                                        self.grid().toggle_bend_at(pointer);
+                                    }
+                                    if merge_click {
+                                        let p = [pointer.x, pointer.y];
+                                        if let Some(from) = self.merge_start.take() {
+                                            self.grid().merges.push(Merge { from, to: p });
+                                        } else if !self.grid().unmerge_at(p) {
+                                            self.merge_start = Some(p);
+                                        }
                                     }
                                 }
                             }
 
                             let plot_img =
-                                PlotImage::new(texture, PlotPoint::new(0.5, 0.5), vec2(1.0, 1.0));
+                                PlotImage::new(&texture, PlotPoint::new(0.5, 0.5), vec2(1.0, 1.0));
                             pui.image(plot_img);
 
                             drag_enabled = !(middle_held || zooming);
 
-                            if middle_held {
+                            if middle_held && !editing_perspective {
                                 // shift all
                                 drag_enabled = false;
-                                let dd = pui.pointer_coordinate_drag_delta();
-                                for v in self.grid.verticals.iter_mut() {
-                                    v.translate(dd)
-                                }
-                                for h in self.grid.horizontals.iter_mut() {
-                                    h.translate(dd)
-                                }
+                                self.grid().translate(pui.pointer_coordinate_drag_delta());
                             }
 
                             SHARED_STATE.with_borrow_mut(|ss| {
                                 ss.drag_enabled = drag_enabled;
                                 ss.delta_y = ss.delta_x * (texture.aspect_ratio() as f64);
                             });
-                            self.grid.plot_inspect(pui);
+                            if editing_perspective {
+                                self.image.as_mut().unwrap().plot_corners(pui);
+                                return;
+                            }
+                            SHARED_STATE.with_borrow(|ss| {
+                                let color = ss.separator_color.gamma_multiply(0.4);
+                                for (k, named) in self.grids.iter().enumerate() {
+                                    if k != self.selected {
+                                        named.grid.plot_outline(pui, color);
+                                    }
+                                }
+                            });
+                            self.grid().plot_inspect(pui);
+                            if let Some(from) = self.merge_start {
+                                pui.points(
+                                    Points::new(vec![from])
+                                        .radius(4.0)
+                                        .color(SHARED_STATE.with_borrow(|ss| ss.separator_color)),
+                                );
+                            }
                         });
                 });
             } else {
@@ -662,6 +2311,13 @@ impl egui_inspect::eframe::App for TableGrid {
 }
 
 fn main() -> egui_inspect::eframe::Result<()> {
+    if let Some(command) = cli::Cli::parse().command {
+        if let Err(e) = cli::run(command) {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     egui_inspect::eframe::run_native(
         "Table OCR",
         Default::default(),
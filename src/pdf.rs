@@ -0,0 +1,343 @@
+//! PDF input. Pages are rasterized from the scan they embed (the largest image on
+//! the page), resampled to the page size at the chosen resolution, which covers
+//! the PDFs produced by scanners and most archives of scanned tables.
+
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use egui_inspect::egui::ColorImage;
+use image::{imageops, DynamicImage, ImageError, RgbaImage};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::img_to_cim;
+
+pub const DEFAULT_DPI: f32 = 200.0;
+
+#[derive(Debug)]
+pub enum PdfError {
+    Pdf(lopdf::Error),
+    Image(ImageError),
+    /// The page (from 1) embeds no image to rasterize.
+    NoImage(usize),
+    /// Image encoding or color space not handled.
+    Unsupported(String),
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfError::Pdf(e) => write!(f, "{e}"),
+            PdfError::Image(e) => write!(f, "{e}"),
+            PdfError::NoImage(page) => write!(f, "page {page} contains no scanned image"),
+            PdfError::Unsupported(what) => write!(f, "unsupported PDF image ({what})"),
+        }
+    }
+}
+
+impl Error for PdfError {}
+
+impl From<lopdf::Error> for PdfError {
+    fn from(e: lopdf::Error) -> Self {
+        PdfError::Pdf(e)
+    }
+}
+
+impl From<ImageError> for PdfError {
+    fn from(e: ImageError) -> Self {
+        PdfError::Image(e)
+    }
+}
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+}
+
+pub struct PdfDocument {
+    pub path: PathBuf,
+    doc: Document,
+    pages: Vec<ObjectId>,
+}
+
+/// Looks up a page attribute, following the page tree up for inheritable ones.
+fn page_attr<'a>(doc: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut dict = page;
+    for _ in 0..32 {
+        if let Ok(value) = dict.get_deref(key, doc) {
+            return Some(value);
+        }
+        dict = dict.get_deref(b"Parent", doc).ok()?.as_dict().ok()?;
+    }
+    None
+}
+
+/// Numbers of a PDF array, integers or reals.
+fn numbers(doc: &Document, obj: &Object) -> Vec<f32> {
+    obj.as_array()
+        .map(|array| {
+            array
+                .iter()
+                .filter_map(|o| doc.dereference(o).ok()?.1.as_float().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Image stream data with the general filters undone, and the image specific
+/// filter (e.g. DCTDecode for JPEG) still to be decoded, if any.
+fn image_data(stream: &Stream) -> Result<(Vec<u8>, Option<String>), PdfError> {
+    let mut filters = stream.filters().unwrap_or_default();
+    let image_filter = match filters.last().map(String::as_str) {
+        Some("DCTDecode" | "JPXDecode" | "CCITTFaxDecode" | "JBIG2Decode" | "RunLengthDecode") => {
+            filters.pop()
+        }
+        _ => None,
+    };
+    if filters.is_empty() {
+        return Ok((stream.content.clone(), image_filter));
+    }
+    // lopdf declines to decompress image streams, so decompress a copy that is not
+    // marked as one, with only the general filters
+    let mut dict = stream.dict.clone();
+    dict.remove(b"Subtype");
+    let filters = filters.into_iter().map(|f| Object::Name(f.into_bytes()));
+    dict.set("Filter", Object::Array(filters.collect()));
+    let data = Stream::new(dict, stream.content.clone()).decompressed_content()?;
+    Ok((data, image_filter))
+}
+
+/// How raw samples map to colors.
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Palette of the base space's colors, as rgb.
+    Indexed(Vec<[u8; 3]>),
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed(_) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    fn rgb(&self, samples: &[u8]) -> [u8; 3] {
+        match self {
+            ColorSpace::Gray => [samples[0]; 3],
+            ColorSpace::Rgb => [samples[0], samples[1], samples[2]],
+            ColorSpace::Cmyk => {
+                let k = 255 - samples[3] as u32;
+                let c = |x: u8| ((255 - x as u32) * k / 255) as u8;
+                [c(samples[0]), c(samples[1]), c(samples[2])]
+            }
+            ColorSpace::Indexed(palette) => {
+                palette.get(samples[0] as usize).copied().unwrap_or([0; 3])
+            }
+        }
+    }
+
+    fn new(doc: &Document, obj: &Object) -> Result<Self, PdfError> {
+        let obj = doc.dereference(obj)?.1;
+        let unsupported = || PdfError::Unsupported(format!("color space {obj:?}"));
+        if let Ok(name) = obj.as_name() {
+            return match name {
+                b"DeviceGray" | b"CalGray" | b"G" => Ok(ColorSpace::Gray),
+                b"DeviceRGB" | b"CalRGB" | b"RGB" => Ok(ColorSpace::Rgb),
+                b"DeviceCMYK" | b"CMYK" => Ok(ColorSpace::Cmyk),
+                _ => Err(unsupported()),
+            };
+        }
+        let array = obj.as_array()?;
+        let family = array.first().ok_or_else(unsupported)?.as_name()?;
+        match family {
+            b"CalGray" => Ok(ColorSpace::Gray),
+            b"CalRGB" => Ok(ColorSpace::Rgb),
+            b"ICCBased" => {
+                let profile = doc.dereference(array.get(1).ok_or_else(unsupported)?)?.1;
+                match profile.as_stream()?.dict.get(b"N")?.as_i64()? {
+                    1 => Ok(ColorSpace::Gray),
+                    3 => Ok(ColorSpace::Rgb),
+                    4 => Ok(ColorSpace::Cmyk),
+                    _ => Err(unsupported()),
+                }
+            }
+            b"Indexed" | b"I" => {
+                let base = ColorSpace::new(doc, array.get(1).ok_or_else(unsupported)?)?;
+                let lookup = doc.dereference(array.get(3).ok_or_else(unsupported)?)?.1;
+                let table = match lookup {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => image_data(stream)?.0,
+                    _ => return Err(unsupported()),
+                };
+                let palette = table
+                    .chunks_exact(base.components())
+                    .map(|c| base.rgb(c))
+                    .collect();
+                Ok(ColorSpace::Indexed(palette))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// Decodes uncompressed samples of `bpc` bits.
+fn decode_samples(
+    data: &[u8],
+    [width, height]: [usize; 2],
+    bpc: usize,
+    space: &ColorSpace,
+    invert: bool,
+) -> Result<RgbaImage, PdfError> {
+    if ![1, 2, 4, 8, 16].contains(&bpc) {
+        return Err(PdfError::Unsupported(format!("{bpc} bits per component")));
+    }
+    let comps = space.components();
+    let stride = (width * comps * bpc).div_ceil(8);
+    if data.len() < stride * height {
+        return Err(PdfError::Unsupported("truncated image data".to_string()));
+    }
+    let max = (1u32 << bpc.min(8)) - 1;
+    let indexed = matches!(space, ColorSpace::Indexed(_));
+    let sample = |row: &[u8], k: usize| -> u8 {
+        let v = match bpc {
+            16 => row[2 * k] as u32,
+            8 => row[k] as u32,
+            _ => {
+                let bit = k * bpc;
+                (row[bit / 8] as u32 >> (8 - bpc - bit % 8)) & max
+            }
+        };
+        let v = match indexed {
+            true => v,
+            false => v * 255 / max,
+        };
+        match invert {
+            true => 255 - v as u8,
+            false => v as u8,
+        }
+    };
+    let mut img = RgbaImage::new(width as u32, height as u32);
+    let mut samples = vec![0; comps];
+    for (j, row) in data.chunks(stride).take(height).enumerate() {
+        for i in 0..width {
+            for (c, s) in samples.iter_mut().enumerate() {
+                *s = sample(row, i * comps + c);
+            }
+            let [r, g, b] = space.rgb(&samples);
+            img.put_pixel(i as u32, j as u32, image::Rgba([r, g, b, 255]));
+        }
+    }
+    Ok(img)
+}
+
+/// Decodes an image XObject.
+fn decode_image(doc: &Document, stream: &Stream) -> Result<RgbaImage, PdfError> {
+    let dict = &stream.dict;
+    let (data, filter) = image_data(stream)?;
+    match filter.as_deref() {
+        Some("DCTDecode") => {
+            let img = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)?;
+            return Ok(img.to_rgba8());
+        }
+        Some(filter) => return Err(PdfError::Unsupported(filter.to_string())),
+        None => {}
+    }
+
+    let int = |key: &[u8]| -> Option<usize> {
+        Some(dict.get_deref(key, doc).ok()?.as_i64().ok()? as usize)
+    };
+    let size = [int(b"Width").unwrap_or(0), int(b"Height").unwrap_or(0)];
+    let is_mask = dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false);
+    let space = match (is_mask, dict.get(b"ColorSpace")) {
+        (false, Ok(obj)) => ColorSpace::new(doc, obj)?,
+        _ => ColorSpace::Gray,
+    };
+    let bpc = match is_mask {
+        true => 1,
+        false => int(b"BitsPerComponent").unwrap_or(8),
+    };
+    // a decode array of [1 0] inverts gray samples, as is common for bilevel scans
+    let decode = dict
+        .get_deref(b"Decode", doc)
+        .map(|d| numbers(doc, d))
+        .unwrap_or_default();
+    let invert = matches!(space, ColorSpace::Gray) && decode.len() >= 2 && decode[0] > decode[1];
+    decode_samples(&data, size, bpc, &space, invert)
+}
+
+impl PdfDocument {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PdfError> {
+        let doc = Document::load(&path)?;
+        let pages = doc.get_pages().into_values().collect();
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            doc,
+            pages,
+        })
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Largest image drawn by the page, i.e. its scan.
+    fn page_scan<'a>(&'a self, page: &'a Dictionary) -> Option<&'a Stream> {
+        let resources = page_attr(&self.doc, page, b"Resources")?.as_dict().ok()?;
+        let xobjects = resources
+            .get_deref(b"XObject", &self.doc)
+            .ok()?
+            .as_dict()
+            .ok()?;
+        xobjects
+            .iter()
+            .filter_map(|(_, obj)| self.doc.dereference(obj).ok()?.1.as_stream().ok())
+            .filter(|stream| {
+                stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image")
+            })
+            .max_by_key(|stream| {
+                let int = |key: &[u8]| stream.dict.get(key).and_then(Object::as_i64).unwrap_or(0);
+                int(b"Width") * int(b"Height")
+            })
+    }
+
+    /// Rasterizes page `page` (from 1) at `dpi`, from its scan stretched over the
+    /// page's media box and turned by its rotation.
+    pub fn render_page(&self, page: usize, dpi: f32) -> Result<ColorImage, PdfError> {
+        let id = *self
+            .pages
+            .get(page.wrapping_sub(1))
+            .ok_or(PdfError::Pdf(lopdf::Error::PageNumberNotFound(page as u32)))?;
+        let dict = self.doc.get_dictionary(id)?;
+        let scan = self.page_scan(dict).ok_or(PdfError::NoImage(page))?;
+        let mut img = decode_image(&self.doc, scan)?;
+
+        let media_box = page_attr(&self.doc, dict, b"MediaBox")
+            .map(|b| numbers(&self.doc, b))
+            .unwrap_or_default();
+        let rotate = page_attr(&self.doc, dict, b"Rotate")
+            .and_then(|r| r.as_i64().ok())
+            .unwrap_or(0)
+            .rem_euclid(360);
+        if let [x0, y0, x1, y1] = media_box[..] {
+            let px = |pt: f32| ((pt.abs() / 72.0 * dpi).round() as u32).max(1);
+            img = imageops::resize(&img, px(x1 - x0), px(y1 - y0), imageops::Triangle);
+        }
+        img = match rotate {
+            90 => imageops::rotate90(&img),
+            180 => imageops::rotate180(&img),
+            270 => imageops::rotate270(&img),
+            _ => img,
+        };
+        Ok(img_to_cim(DynamicImage::ImageRgba8(img)))
+    }
+}
@@ -0,0 +1,196 @@
+//! Proofreading of an extracted table cell by cell, each cell's text next to its
+//! enlarged crop.
+
+use egui_inspect::egui::{
+    self, ColorImage, DragValue, Id, Key, TextEdit, TextureHandle, TextureOptions, Window,
+};
+
+use crate::{confidence::LOW_CONFIDENCE, crop_cell, CellSpan, CellStatus, Grid, TableEdit};
+
+/// Width cell crops are enlarged to, up to `MAX_ZOOM` times their size.
+const CROP_WIDTH: f32 = 600.0;
+const MAX_ZOOM: f32 = 4.0;
+
+static REVIEW_KEYS: &str =
+    "Enter: accept and go to next, E: edit, ←/→: previous/next, Esc: stop editing";
+
+/// Queue of cells to proofread, by default those whose confidence is below a
+/// threshold.
+pub struct Review {
+    pub open: bool,
+    threshold: f32,
+    /// Queue every non-empty cell rather than the low confidence ones.
+    all_non_empty: bool,
+    queue: Vec<(usize, usize)>,
+    position: usize,
+    /// Enlarged crop of the cell being reviewed.
+    crop: Option<((usize, usize), TextureHandle)>,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Self {
+            open: false,
+            threshold: LOW_CONFIDENCE,
+            all_non_empty: false,
+            queue: vec![],
+            position: 0,
+            crop: None,
+        }
+    }
+}
+
+impl Review {
+    /// Opens the review on the cells of `table` to proofread, merged regions by
+    /// their top left cell.
+    pub fn start(&mut self, table: &TableEdit) {
+        self.queue = (0..table.items.len())
+            .flat_map(|i| (0..table.items[i].len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| !table.is_covered(i, j))
+            .filter(|&(i, j)| match self.all_non_empty {
+                true => !table.items[i][j].is_empty(),
+                false => table.confidence[i][j].is_some_and(|c| c < self.threshold),
+            })
+            .collect();
+        self.position = 0;
+        self.crop = None;
+        self.open = true;
+    }
+
+    fn go_to(&mut self, position: usize) {
+        self.position = position.min(self.queue.len());
+    }
+
+    fn crop_tex(
+        &mut self,
+        ctx: &egui::Context,
+        cell: (usize, usize),
+        table: &TableEdit,
+        grid: &Grid,
+        cim: &ColorImage,
+    ) -> Option<&TextureHandle> {
+        if self.crop.as_ref().map(|(c, _)| *c) != Some(cell) {
+            let (i, j) = cell;
+            let span = table
+                .span_at(i, j)
+                .copied()
+                .unwrap_or(CellSpan::single(i, j));
+            let (buff, size) = crop_cell(cim, grid, &span);
+            self.crop = (size[0] > 0 && size[1] > 0).then(|| {
+                let crop = ColorImage::from_rgba_unmultiplied(size, &buff);
+                (
+                    cell,
+                    ctx.load_texture("review_crop", crop, TextureOptions::NEAREST),
+                )
+            });
+        }
+        self.crop.as_ref().map(|(_, tex)| tex)
+    }
+
+    /// Shows the review window of a table, expects `grid` (sorted) to match it.
+    /// `id` is the table's, stable whatever its name.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        id: Id,
+        name: &str,
+        table: &mut TableEdit,
+        grid: &Grid,
+        cim: &ColorImage,
+    ) {
+        let edit_id = id.with("review_edit");
+        let editing = ctx.memory(|m| m.has_focus(edit_id));
+        // shortcuts are left alone while typing in any other field
+        let shortcuts = editing || !ctx.wants_keyboard_input();
+        let (enter, edit, previous, next) = ctx.input(|r| {
+            (
+                shortcuts && r.key_pressed(Key::Enter),
+                shortcuts && !editing && r.key_pressed(Key::E),
+                shortcuts && !editing && r.key_pressed(Key::ArrowLeft),
+                shortcuts && !editing && r.key_pressed(Key::ArrowRight),
+            )
+        });
+
+        let mut open = self.open;
+        Window::new(format!("Review: {name}"))
+            .id(id.with("review"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add_enabled(
+                        !self.all_non_empty,
+                        DragValue::new(&mut self.threshold)
+                            .range(0.0..=100.0)
+                            .prefix("confidence < ")
+                            .suffix("%"),
+                    );
+                    ui.checkbox(&mut self.all_non_empty, "All non-empty cells");
+                    if ui.button("Restart").clicked() {
+                        self.start(table);
+                    }
+                });
+                ui.weak(REVIEW_KEYS);
+                ui.separator();
+
+                let Some(&(i, j)) = self.queue.get(self.position) else {
+                    match self.queue.is_empty() {
+                        true => ui.label("No cells to review."),
+                        false => ui.label(format!("Reviewed {} cell(s).", self.queue.len())),
+                    };
+                    if previous && !self.queue.is_empty() {
+                        self.go_to(self.queue.len() - 1);
+                    }
+                    return;
+                };
+
+                let mut heading = format!(
+                    "Cell {i},{j} ({} of {})",
+                    self.position + 1,
+                    self.queue.len()
+                );
+                if let Some(c) = table.confidence[i][j] {
+                    heading += &format!(", confidence {c:.0}%");
+                }
+                ui.label(heading);
+                match self.crop_tex(ui.ctx(), (i, j), table, grid, cim) {
+                    Some(tex) => {
+                        let size = tex.size_vec2();
+                        let zoom = (CROP_WIDTH / size.x).min(MAX_ZOOM);
+                        ui.image((tex.id(), size * zoom));
+                    }
+                    None => {
+                        ui.weak("(empty crop)");
+                    }
+                }
+                let response = ui.add(
+                    TextEdit::singleline(&mut table.items[i][j])
+                        .id(edit_id)
+                        .desired_width(CROP_WIDTH),
+                );
+                // focused after drawing, so that the key is not typed in
+                if edit {
+                    response.request_focus();
+                }
+
+                ui.horizontal(|ui| {
+                    let accept = ui.button("Accept").clicked() || enter;
+                    if ui.button("Previous").clicked() || previous {
+                        self.go_to(self.position.saturating_sub(1));
+                    }
+                    if ui.button("Next").clicked() || next {
+                        self.go_to(self.position + 1);
+                    }
+                    if accept {
+                        // proofread, no longer an OCR guess
+                        table.confidence[i][j] = None;
+                        table.status[i][j] = match table.items[i][j].is_empty() {
+                            true => CellStatus::Empty,
+                            false => CellStatus::Ok,
+                        };
+                        self.go_to(self.position + 1);
+                    }
+                });
+            });
+        self.open = open;
+    }
+}
@@ -0,0 +1,87 @@
+//! Grid templates, for reusing the annotation of a recurring table layout.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{self, BackendConfig},
+    detect::{self, InkMask},
+    error::{AtPath, Error, Result},
+    Grid, MAX_ROTATION,
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TemplateGrid {
+    pub name: String,
+    pub grid: Grid,
+}
+
+/// Summary of the image a template was made on, for registering the template onto
+/// new scans of the same layout.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Reference {
+    /// Rotation that levels the reference image, as estimated by auto deskew.
+    pub level_theta: f32,
+    /// Ink projection profiles of the rotated reference image.
+    pub cols: Vec<f32>,
+    pub rows: Vec<f32>,
+}
+
+/// Number of bands in the reference profiles.
+pub const PROFILE_BINS: usize = 500;
+
+impl Reference {
+    pub fn new(base: &InkMask, rectified: &InkMask) -> Self {
+        let (cols, rows) = detect::ink_profiles(rectified, PROFILE_BINS);
+        Self {
+            level_theta: detect::estimate_skew(base, MAX_ROTATION),
+            cols,
+            rows,
+        }
+    }
+}
+
+/// Everything needed to extract a known layout from a new image, the grids (in
+/// the normalized coordinates of the rectified image), the rotation and
+/// perspective correction applied before placing them and the OCR backend.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Template {
+    pub theta: f32,
+    /// Perspective corners in the rotated image, when perspective was corrected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corners: Option<[[f64; 2]; 4]>,
+    #[serde(
+        alias = "cmd_template",
+        deserialize_with = "backend::deserialize_config"
+    )]
+    pub backend: BackendConfig,
+    pub grids: Vec<TemplateGrid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<Reference>,
+}
+
+impl Template {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self).at(path)?).at(path)
+    }
+
+    /// Reads a template, rejecting one without grids or with grids the annotation
+    /// could not handle.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let template: Self = serde_json::from_str(&fs::read_to_string(path).at(path)?).at(path)?;
+        if template.grids.is_empty() {
+            let message = format!("{}: template has no grids", path.display());
+            return Err(Error::Invalid(message));
+        }
+        for tg in template.grids.iter() {
+            if let Err(e) = tg.grid.validate() {
+                let message = format!("{}: grid {:?} {e}", path.display(), tg.name);
+                return Err(Error::Invalid(message));
+            }
+        }
+        Ok(template)
+    }
+}
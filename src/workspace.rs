@@ -0,0 +1,71 @@
+//! Private temporary directories of extractions, holding the files exchanged with
+//! external OCR engines.
+
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::{
+    env,
+    fs::{self, DirBuilder},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    error::{AtPath, Result},
+    CellSpan,
+};
+
+/// Extractions started by this process, for naming their directories.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary directory of a single extraction, so that concurrent extractions
+/// (of other grids or app instances) cannot overwrite each other's files, and
+/// private to the user on unix. Removed when dropped, unless kept for inspecting
+/// what the engine was given.
+pub struct Workspace {
+    pub dir: PathBuf,
+    pub keep: bool,
+}
+
+impl Workspace {
+    pub fn new(keep: bool) -> Result<Self> {
+        loop {
+            let run = RUNS.fetch_add(1, Ordering::Relaxed);
+            let dir = env::temp_dir().join(format!("table_ocr_{}_{run}", process::id()));
+            let mut builder = DirBuilder::new();
+            #[cfg(unix)]
+            builder.mode(0o700);
+            match builder.create(&dir) {
+                // left over from a crashed process that had the same id, or planted by
+                // another user, never reused
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                res => return res.at(&dir).map(|()| Self { dir, keep }),
+            }
+        }
+    }
+
+    /// The directory, when kept after the extraction.
+    pub fn kept_dir(&self) -> Option<&Path> {
+        self.keep.then_some(self.dir.as_path())
+    }
+
+    /// Paths of the image of a cell and of the engine's output for it, without
+    /// extension.
+    pub fn cell_paths(&self, cell: &CellSpan) -> (String, String) {
+        let (i, j) = (cell.row, cell.col);
+        let path = |name: String| self.dir.join(name).to_string_lossy().into_owned();
+        (
+            path(format!("crop_{i}_{j}.png")),
+            path(format!("out_{i}_{j}")),
+        )
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}